@@ -0,0 +1,87 @@
+/*
+ * C interface to the raycast renderer, exported from the shared library
+ * built with the ffi feature, which is written to target/release:
+ *
+ *     cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * Scenes are given as the text of a scene file, as written by Scene::save
+ * or with --export. Functions that can fail return one of the negative
+ * error codes below, and last_error_message describes what went wrong.
+ * Functions returning pointers return NULL instead.
+ * Points and directions are arrays of three floats.
+ */
+#ifndef RAYCAST_H
+#define RAYCAST_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RAYCAST_ERROR_NULL_POINTER -1
+#define RAYCAST_ERROR_INVALID_ARGUMENT -2
+#define RAYCAST_ERROR_INVALID_SCENE -3
+/* Something went wrong inside the renderer, which is a bug */
+#define RAYCAST_ERROR_PANIC -4
+
+typedef struct Scene Scene;
+
+typedef struct RenderOptions {
+    uint32_t width;
+    uint32_t height;
+    uint32_t samples;
+    uint64_t seed;
+    uint32_t max_bounces;
+} RenderOptions;
+
+typedef struct RayHit {
+    float p[3];
+    float n[3];
+    /* Distance along the ray from where it started */
+    float distance;
+    float color[3];
+    /* Id of the object that was hit, or -1 for surfaces without one */
+    int64_t object;
+} RayHit;
+
+/* Message for the last error on this thread, valid until the next call
+ * that fails on it */
+const char *last_error_message(void);
+
+RenderOptions render_options_default(void);
+
+/* Renders the scene into the buffer as 8 bit RGBA, row by row from the
+ * top, which must hold width * height * 4 bytes. Returns 0 on success. */
+int render_scene(const char *scene_json, const RenderOptions *options, uint8_t *out_buffer);
+
+/* Parses a scene for repeated renders and queries, returning NULL if it
+ * isn't valid. Free it with scene_free. */
+Scene *scene_from_json(const char *scene_json);
+void scene_free(Scene *scene);
+
+/* As render_scene, for a scene that was already parsed */
+int scene_render(const Scene *scene, const RenderOptions *options, uint8_t *out_buffer);
+
+/* Casts a ray from the point along the direction, which doesn't need to be
+ * normalized. Returns 1 and fills in the hit if it meets a surface, and 0
+ * if it doesn't. */
+int scene_cast_ray(const Scene *scene, const float from[3], const float dir[3], RayHit *hit);
+
+/* Number of steps the ray takes until it meets a surface or gives up */
+int64_t scene_ray_steps(const Scene *scene, const float from[3], const float dir[3]);
+
+/* Where the ray first leaves the object it starts in, or where it starts
+ * if that is outside. Returns 1 and fills in the point if it does, and 0
+ * if it doesn't leave within the scene. */
+int scene_leave(const Scene *scene, const float from[3], const float dir[3], float out[3]);
+
+/* Normal of the surface at the point, estimated over the precision rays
+ * have after traveling the distance. Returns 0 on success. */
+int scene_normal(const Scene *scene, const float p[3], float distance, float out[3]);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,301 @@
+use ultraviolet::{Bivec3, Rotor3, Vec3};
+
+use crate::distfield::{capsule, displacement, mandelbulb, warp, Sample, Sdf, Surface};
+use crate::json::Json;
+
+const STACK_SIZE: usize = 16;
+const POINT_STACK_SIZE: usize = 8;
+
+// Instructions for a small stack machine. Shapes push a sample evaluated at
+// the current point, operators combine the samples on top of the stack and
+// point instructions push or pop the point that shapes are evaluated at.
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+        surface: Surface,
+    },
+    Capsule {
+        a: Vec3,
+        b: Vec3,
+        radius: f32,
+        surface: Surface,
+    },
+    Mandelbulb {
+        center: Vec3,
+        scale: f32,
+        power: f32,
+        surface: Surface,
+    },
+    Union,
+    Intersect,
+    Invert,
+    Displace {
+        scale: f32,
+        detail: f32,
+    },
+    PushWarp,
+    PushTransform {
+        offset: Vec3,
+        rotation: Rotor3,
+        scale: f32,
+    },
+    PopTransform {
+        scale: f32,
+    },
+}
+
+pub struct Program {
+    ops: Vec<Op>,
+}
+
+impl Program {
+    pub fn compile(root: &dyn Sdf) -> Option<Self> {
+        let mut ops = Vec::new();
+        root.compile(&mut ops)?;
+        Self::from_ops(ops)
+    }
+
+    pub fn from_ops(ops: Vec<Op>) -> Option<Self> {
+        // Reject programs that would overflow the fixed size stacks, or take
+        // more off them than is there, such as ops loaded from a scene file
+        let (mut samples, mut points) = (0usize, 1usize);
+        for op in ops.iter() {
+            match op {
+                Op::Sphere { .. } | Op::Capsule { .. } | Op::Mandelbulb { .. } => samples += 1,
+                Op::Union | Op::Intersect if samples >= 2 => samples -= 1,
+                Op::Invert | Op::Displace { .. } if samples >= 1 => {}
+                Op::PushWarp | Op::PushTransform { .. } => points += 1,
+                // Scales the sample on top, and can't pop the original point
+                Op::PopTransform { .. } if samples >= 1 && points >= 2 => points -= 1,
+                _ => return None,
+            }
+            if samples > STACK_SIZE || points > POINT_STACK_SIZE {
+                return None;
+            }
+        }
+        if samples != 1 || points != 1 || ops.len() > u16::MAX as usize {
+            return None;
+        }
+        Some(Self { ops })
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+}
+
+// As saved in scene files, one object per op named by "op"
+impl Op {
+    pub(crate) fn to_json(self) -> Json {
+        match self {
+            Op::Sphere {
+                center,
+                radius,
+                surface,
+            } => Json::object([
+                ("op", "sphere".into()),
+                ("center", center.into()),
+                ("radius", radius.into()),
+                ("surface", surface.to_json()),
+            ]),
+            Op::Capsule {
+                a,
+                b,
+                radius,
+                surface,
+            } => Json::object([
+                ("op", "capsule".into()),
+                ("a", a.into()),
+                ("b", b.into()),
+                ("radius", radius.into()),
+                ("surface", surface.to_json()),
+            ]),
+            Op::Mandelbulb {
+                center,
+                scale,
+                power,
+                surface,
+            } => Json::object([
+                ("op", "mandelbulb".into()),
+                ("center", center.into()),
+                ("scale", scale.into()),
+                ("power", power.into()),
+                ("surface", surface.to_json()),
+            ]),
+            Op::Union => Json::object([("op", "union".into())]),
+            Op::Intersect => Json::object([("op", "intersect".into())]),
+            Op::Invert => Json::object([("op", "invert".into())]),
+            Op::Displace { scale, detail } => Json::object([
+                ("op", "displace".into()),
+                ("scale", scale.into()),
+                ("detail", detail.into()),
+            ]),
+            Op::PushWarp => Json::object([("op", "push_warp".into())]),
+            Op::PushTransform {
+                offset,
+                rotation,
+                scale,
+            } => {
+                // Scalar part followed by the xy, xz and yz bivector parts
+                let bv = rotation.bv;
+                let rotation = [rotation.s, bv.xy, bv.xz, bv.yz];
+                Json::object([
+                    ("op", "push_transform".into()),
+                    ("offset", offset.into()),
+                    ("rotation", Json::Array(rotation.map(Json::from).to_vec())),
+                    ("scale", scale.into()),
+                ])
+            }
+            Op::PopTransform { scale } => {
+                Json::object([("op", "pop_transform".into()), ("scale", scale.into())])
+            }
+        }
+    }
+
+    pub(crate) fn from_json(json: &Json) -> Result<Self, String> {
+        let surface = || json.required("surface", Some).and_then(Surface::from_json);
+        let rotation = |json: &Json| match json.array()? {
+            [s, xy, xz, yz] => Some(Rotor3::new(
+                s.f32()?,
+                Bivec3::new(xy.f32()?, xz.f32()?, yz.f32()?),
+            )),
+            _ => None,
+        };
+        Ok(match json.required("op", Json::str)? {
+            "sphere" => Op::Sphere {
+                center: json.required("center", Json::vec3)?,
+                radius: json.required("radius", Json::f32)?,
+                surface: surface()?,
+            },
+            "capsule" => Op::Capsule {
+                a: json.required("a", Json::vec3)?,
+                b: json.required("b", Json::vec3)?,
+                radius: json.required("radius", Json::f32)?,
+                surface: surface()?,
+            },
+            "mandelbulb" => Op::Mandelbulb {
+                center: json.required("center", Json::vec3)?,
+                scale: json.required("scale", Json::f32)?,
+                power: json.required("power", Json::f32)?,
+                surface: surface()?,
+            },
+            "union" => Op::Union,
+            "intersect" => Op::Intersect,
+            "invert" => Op::Invert,
+            "displace" => Op::Displace {
+                scale: json.required("scale", Json::f32)?,
+                detail: json.required("detail", Json::f32)?,
+            },
+            "push_warp" => Op::PushWarp,
+            "push_transform" => Op::PushTransform {
+                offset: json.required("offset", Json::vec3)?,
+                rotation: json.required("rotation", rotation)?,
+                scale: json.required("scale", Json::f32)?,
+            },
+            "pop_transform" => Op::PopTransform {
+                scale: json.required("scale", Json::f32)?,
+            },
+            op => return Err(format!("unknown op: {}", op)),
+        })
+    }
+}
+
+impl Program {
+    fn surface(&self, index: usize) -> Surface {
+        match self.ops[index] {
+            Op::Sphere { surface, .. }
+            | Op::Capsule { surface, .. }
+            | Op::Mandelbulb { surface, .. } => surface,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Sdf for Program {
+    fn sample(&self, p: Vec3) -> Sample {
+        // Only distances and the index of the shape that produced them are
+        // kept on the stack, the surface is looked up once at the end
+        let mut distances = [0f32; STACK_SIZE];
+        let mut shapes = [0u16; STACK_SIZE];
+        let mut points = [p; POINT_STACK_SIZE];
+        let (mut top, mut point) = (0, 0);
+        let mut p = p;
+        for (index, op) in self.ops.iter().enumerate() {
+            match *op {
+                Op::Sphere { center, radius, .. } => {
+                    distances[top] = (p - center).mag() - radius;
+                    shapes[top] = index as u16;
+                    top += 1;
+                }
+                Op::Capsule {
+                    a,
+                    b,
+                    radius,
+                    surface,
+                } => {
+                    distances[top] = capsule(p, a, b, radius, surface).distance;
+                    shapes[top] = index as u16;
+                    top += 1;
+                }
+                Op::Mandelbulb {
+                    center,
+                    scale,
+                    power,
+                    surface,
+                } => {
+                    distances[top] = mandelbulb(p, center, scale, power, surface).distance;
+                    shapes[top] = index as u16;
+                    top += 1;
+                }
+                Op::Union => {
+                    top -= 1;
+                    if distances[top - 1] >= distances[top] {
+                        distances[top - 1] = distances[top];
+                        shapes[top - 1] = shapes[top];
+                    }
+                }
+                Op::Intersect => {
+                    top -= 1;
+                    if distances[top - 1] < distances[top] {
+                        distances[top - 1] = distances[top];
+                        shapes[top - 1] = shapes[top];
+                    }
+                }
+                Op::Invert => distances[top - 1] = -distances[top - 1],
+                Op::Displace { scale, detail } => {
+                    distances[top - 1] += displacement(p, scale, detail);
+                }
+                Op::PushWarp => {
+                    point += 1;
+                    p = warp(p);
+                    points[point] = p;
+                }
+                Op::PushTransform {
+                    offset,
+                    rotation,
+                    scale,
+                } => {
+                    point += 1;
+                    p = rotation.reversed() * (p - offset) / scale;
+                    points[point] = p;
+                }
+                Op::PopTransform { scale } => {
+                    point -= 1;
+                    p = points[point];
+                    distances[top - 1] *= scale;
+                }
+            }
+        }
+        Sample {
+            distance: distances[0],
+            surface: self.surface(shapes[0] as usize),
+        }
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        ops.extend_from_slice(&self.ops);
+        Some(())
+    }
+}
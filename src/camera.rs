@@ -0,0 +1,201 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use ultraviolet::Vec3;
+
+// The camera sits here, looking along +z with y up
+pub const EYE: Vec3 = Vec3::new(0., 0., -100.);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lens {
+    #[default]
+    Perspective,
+    // The full sphere around the eye, with longitude across the image and
+    // latitude down it. Best rendered at twice as wide as high.
+    Equirectangular,
+    // Equidistant fisheye covering the half sphere in front of the eye, in
+    // a circle filling the smaller image dimension
+    Fisheye,
+    // Stereographic projection looking straight down, which wraps the
+    // ground into a "little planet" with the horizon halfway to the edge
+    Stereographic,
+}
+
+impl Lens {
+    // Direction of the ray through pixel (x, y), counted from the top left,
+    // offset by the jitter in pixels. None outside of the lens' image circle.
+    pub fn direction(
+        &self,
+        (width, height): (u32, u32),
+        (x, y): (u32, u32),
+        (jx, jy): (f32, f32),
+    ) -> Option<Vec3> {
+        // Offset from the center with y up, in units of half the smaller dimension
+        let half = width.min(height) as f32 * 0.5;
+        let dx = (x as f32 + 0.5 + jx - width as f32 * 0.5) / half;
+        let dy = (height as f32 * 0.5 - y as f32 - 0.5 - jy) / half;
+        let (r, phi) = ((dx * dx + dy * dy).sqrt(), dy.atan2(dx));
+        let dir = match self {
+            Lens::Perspective => {
+                let center = Vec3::new(width as _, height as _, 0.0) * 0.5;
+                let p_img = Vec3::new(x as f32 + jx, (height - y) as f32 + jy, 0.0);
+                let p_scaled = (p_img - center) / width.min(height) as f32 * 250.;
+                (p_scaled - EYE).normalized()
+            }
+            Lens::Equirectangular => {
+                let u = (x as f32 + 0.5 + jx) / width as f32;
+                let v = (y as f32 + 0.5 + jy) / height as f32;
+                let longitude = (u - 0.5) * 2. * PI;
+                let latitude = (0.5 - v) * PI;
+                Vec3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    latitude.cos() * longitude.cos(),
+                )
+            }
+            Lens::Fisheye => {
+                if r > 1. {
+                    return None;
+                }
+                let theta = r * PI * 0.5;
+                Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                )
+            }
+            Lens::Stereographic => {
+                // The horizon is at 2 in the usual projection
+                let theta = 2. * (r * 2.).atan();
+                Vec3::new(
+                    theta.sin() * phi.cos(),
+                    -theta.cos(),
+                    theta.sin() * phi.sin(),
+                )
+            }
+        };
+        Some(dir)
+    }
+}
+
+impl fmt::Display for Lens {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Lens::Perspective => "perspective",
+            Lens::Equirectangular => "equirect",
+            Lens::Fisheye => "fisheye",
+            Lens::Stereographic => "stereographic",
+        })
+    }
+}
+
+impl FromStr for Lens {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perspective" => Ok(Lens::Perspective),
+            "equirect" => Ok(Lens::Equirectangular),
+            "fisheye" => Ok(Lens::Fisheye),
+            "stereographic" => Ok(Lens::Stereographic),
+            _ => Err(format!("unknown lens: {}", s)),
+        }
+    }
+}
+
+// Default distance between the eyes for stereo, in scene units
+pub const DEFAULT_IPD: f32 = 4.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StereoLayout {
+    SideBySide,
+    TopBottom,
+}
+
+// Left and right views in one frame, with the left eye on the left or on
+// top, each taking half of it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    pub ipd: f32,
+}
+
+impl Stereo {
+    pub fn new(layout: StereoLayout) -> Self {
+        Self {
+            layout,
+            ipd: DEFAULT_IPD,
+        }
+    }
+
+    // Size of each view for the full frame size
+    pub fn view_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self.layout {
+            StereoLayout::SideBySide => (width / 2, height),
+            StereoLayout::TopBottom => (width, height / 2),
+        }
+    }
+
+    // The pixel within its view, and -1 for the left eye or 1 for the right
+    pub(crate) fn view_pixel(&self, size: (u32, u32), (x, y): (u32, u32)) -> ((u32, u32), f32) {
+        let (view_width, view_height) = self.view_size(size);
+        match self.layout {
+            StereoLayout::SideBySide if x >= view_width => ((x - view_width, y), 1.),
+            StereoLayout::TopBottom if y >= view_height => ((x, y - view_height), 1.),
+            _ => ((x, y), -1.),
+        }
+    }
+
+    // Eye position for a ray in direction dir. Panoramas move the eye
+    // sideways to each ray (omni-directional stereo), so that the views
+    // stay apart all the way around.
+    pub(crate) fn eye(&self, lens: Lens, dir: Vec3, side: f32) -> Vec3 {
+        let right = match lens {
+            Lens::Equirectangular => {
+                let horizontal = Vec3::new(dir.z, 0., -dir.x);
+                if horizontal.mag_sq() > 1e-6 {
+                    horizontal.normalized()
+                } else {
+                    Vec3::unit_x()
+                }
+            }
+            _ => Vec3::unit_x(),
+        };
+        EYE + right * (side * self.ipd * 0.5)
+    }
+}
+
+// Formats as the layout, "sbs" or "tb", followed by ":" and the distance
+// between the eyes. Parsing accepts the layout on its own too.
+impl fmt::Display for Stereo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let layout = match self.layout {
+            StereoLayout::SideBySide => "sbs",
+            StereoLayout::TopBottom => "tb",
+        };
+        write!(f, "{}:{}", layout, self.ipd)
+    }
+}
+
+impl FromStr for Stereo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (layout, ipd) = match s.split_once(':') {
+            Some((layout, ipd)) => {
+                let ipd = ipd
+                    .parse()
+                    .map_err(|err| format!("invalid eye distance: {}", err))?;
+                (layout, ipd)
+            }
+            None => (s, DEFAULT_IPD),
+        };
+        let layout = match layout {
+            "sbs" => StereoLayout::SideBySide,
+            "tb" => StereoLayout::TopBottom,
+            _ => return Err(format!("unknown stereo layout: {}", layout)),
+        };
+        Ok(Self { layout, ipd })
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use ultraviolet::Vec3;
+
+use crate::distfield::Surface;
+use crate::light::cone_direction;
+use crate::rng::Rng;
+use crate::{
+    bend, glossy_exponent, guess_normal, leave_medium, precision, raycast, raycast_out, Light,
+    RayKind, Scene,
+};
+
+// Photons are gathered over this radius around shaded points, times the
+// scene's unit scale, unless given otherwise
+pub const DEFAULT_RADIUS: f32 = 5.;
+// Each light's directions are divided into this many cells along each side,
+// to find the ones that lead to reflective surfaces
+const PROJECTION_CELLS: u32 = 128;
+// Photons are followed through at most this many reflections or objects
+const MAX_BOUNCES: usize = 4;
+
+struct Photon {
+    p: Vec3,
+    // Direction it arrived from, so it only lights surfaces facing it
+    dir: Vec3,
+    flux: Vec3,
+}
+
+// Light focused onto surfaces by reflective and refractive objects, which
+// direct lighting can't find. Photons are traced forward from the lights,
+// only towards the reflective or refractive surfaces they can see, and
+// stored where they land after reflecting or passing through. Shading adds
+// the light of the photons nearby.
+pub struct Caustics {
+    radius: f32,
+    photons: Vec<Photon>,
+    // Photon indices by cell of a grid with the radius as spacing
+    cells: HashMap<[i32; 3], Vec<usize>>,
+}
+
+impl Caustics {
+    // Traces about the given number of photons, shared between the lights
+    pub fn trace(scene: &Scene, photons: u32, radius: f32, seed: u64) -> Self {
+        let mut caustics = Self {
+            radius,
+            photons: Vec::new(),
+            cells: HashMap::new(),
+        };
+        let mut rng = Rng::new(seed);
+        let per_light = photons / scene.lights.len().max(1) as u32;
+        for light in &scene.lights {
+            // Projection map of the cells that lead to surfaces redirecting light
+            let size = PROJECTION_CELLS as f32;
+            let cells: Vec<(f32, f32)> = (0..PROJECTION_CELLS * PROJECTION_CELLS)
+                .map(|i| {
+                    (
+                        (i % PROJECTION_CELLS) as f32 / size,
+                        (i / PROJECTION_CELLS) as f32 / size,
+                    )
+                })
+                .filter(|&(u, v)| {
+                    let (origin, dir) = light.emit(scene, (u + 0.5 / size, v + 0.5 / size));
+                    first_hit(scene, origin, dir).is_some_and(|(surface, _)| redirects(&surface))
+                })
+                .collect();
+            if cells.is_empty() || per_light == 0 {
+                continue;
+            }
+            let fraction = cells.len() as f32 / (size * size) / per_light as f32;
+            for _ in 0..per_light {
+                let (u, v) = cells[rng.next_u32() as usize % cells.len()];
+                let jitter = (rng.next_f32() / size, rng.next_f32() / size);
+                let (origin, dir) = light.emit(scene, (u + jitter.0, v + jitter.1));
+                let (p, traveled) = match first_hit(scene, origin, dir) {
+                    Some((surface, p)) if redirects(&surface) => (p, (p - origin).mag()),
+                    _ => continue,
+                };
+                let flux = light.photon_flux(scene, fraction, traveled);
+                caustics.follow(scene, &mut rng, light, (p, dir), traveled, flux);
+            }
+        }
+        for (i, photon) in caustics.photons.iter().enumerate() {
+            let cell = cell(photon.p, radius);
+            caustics.cells.entry(cell).or_default().push(i);
+        }
+        caustics
+    }
+
+    // Reflects a photon off the surface at p or passes it through the
+    // object, storing it wherever it lands and following it further off
+    // surfaces that redirect light
+    fn follow(
+        &mut self,
+        scene: &Scene,
+        rng: &mut Rng,
+        light: &Light,
+        (mut p, mut dir): (Vec3, Vec3),
+        mut traveled: f32,
+        mut flux: Vec3,
+    ) {
+        for _ in 0..MAX_BOUNCES {
+            let surface = scene.sample(p).surface;
+            let n = guess_normal(scene, p, precision(scene, traveled));
+            let mut r = dir.reflected(n);
+            let mut from = None;
+            match surface.ior {
+                // Reflected or let through at random, in proportion
+                Some(ior) => {
+                    let (into, reflected) = bend(dir, -n, 1., ior);
+                    let reflected = surface.reflectivity + (1. - surface.reflectivity) * reflected;
+                    if rng.next_f32() >= reflected {
+                        let (exit, out, transmittance) =
+                            match pass_through(scene, rng, p, into, traveled) {
+                                Some(passed) => passed,
+                                None => return,
+                            };
+                        flux *= transmittance;
+                        from = Some(exit);
+                        r = out;
+                    }
+                }
+                None => flux *= surface.reflectivity,
+            }
+            if surface.roughness > 0. && from.is_none() {
+                let exponent = glossy_exponent(surface.roughness);
+                let cos_theta = rng.next_f32().powf(1. / (exponent + 1.));
+                r = cone_direction(r, cos_theta, 2. * PI * rng.next_f32());
+                if r.dot(n) <= 0. {
+                    return;
+                }
+            }
+            let from = match from.or_else(|| raycast_out(scene, p, r, traveled)) {
+                Some(from) => from,
+                None => return,
+            };
+            let extent = scene.ray_extent(from, r);
+            let (s, next) = match raycast(scene, from, r, RayKind::Reflection, traveled, |q| {
+                (from - q).mag_sq() < extent * extent
+            }) {
+                Some(hit) => hit,
+                None => return,
+            };
+            if light.affects(&s.surface) {
+                self.photons.push(Photon {
+                    p: next,
+                    dir: r,
+                    flux,
+                });
+            }
+            if !redirects(&s.surface) {
+                return;
+            }
+            traveled += (next - p).mag();
+            (p, dir) = (next, r);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    // Light arriving at p on a surface with normal n from the photons
+    // within the radius
+    pub(crate) fn irradiance(&self, p: Vec3, n: Vec3) -> Vec3 {
+        let [x, y, z] = cell(p, self.radius);
+        let mut flux = Vec3::zero();
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let indices = match self.cells.get(&[x + dx, y + dy, z + dz]) {
+                        Some(indices) => indices,
+                        None => continue,
+                    };
+                    for &i in indices {
+                        let photon = &self.photons[i];
+                        if photon.dir.dot(n) < 0.
+                            && (photon.p - p).mag_sq() < self.radius * self.radius
+                        {
+                            flux += photon.flux;
+                        }
+                    }
+                }
+            }
+        }
+        flux / (PI * self.radius * self.radius)
+    }
+}
+
+fn cell(p: Vec3, spacing: f32) -> [i32; 3] {
+    let c = p / spacing;
+    [c.x.floor() as i32, c.y.floor() as i32, c.z.floor() as i32]
+}
+
+fn redirects(surface: &Surface) -> bool {
+    surface.reflectivity > 0. || surface.ior.is_some()
+}
+
+// The first surface a photon meets, and where
+fn first_hit(scene: &Scene, origin: Vec3, dir: Vec3) -> Option<(Surface, Vec3)> {
+    let extent = scene.ray_extent(origin, dir);
+    let (s, p) = raycast(scene, origin, dir, RayKind::Shadow, 0., |q| {
+        (origin - q).mag_sq() < extent * extent
+    })?;
+    Some((s.surface, p))
+}
+
+// Follows a photon that has bent into a refractive object at from until it
+// comes out, through any other refractive objects inside, reflecting past
+// the critical angle or at random in proportion to the reflected light.
+// Returns where it comes out, in which direction, and how much of it is
+// left after absorption. Photons that meet opaque objects inside are lost.
+fn pass_through(
+    scene: &Scene,
+    rng: &mut Rng,
+    from: Vec3,
+    dir: Vec3,
+    traveled: f32,
+) -> Option<(Vec3, Vec3, Vec3)> {
+    let (mut from, mut dir, mut traveled) = (from, dir, traveled);
+    let mut transmittance = Vec3::one();
+    for _ in 0..MAX_BOUNCES {
+        let surface = scene.sample(from).surface;
+        let crossing = leave_medium(scene, from, dir, traveled)?;
+        let distance = (crossing.inside - from).mag();
+        traveled += distance;
+        if let Some(absorption) = surface.absorption {
+            transmittance *= absorption.transmittance(distance);
+        }
+        let next_ior = match crossing.next {
+            Some(next) => next.ior?,
+            None => 1.,
+        };
+        let (out, reflected) = bend(dir, crossing.n, surface.ior.unwrap_or(1.), next_ior);
+        if rng.next_f32() >= reflected {
+            if crossing.next.is_none() {
+                return Some((crossing.beyond, out, transmittance));
+            }
+            (from, dir) = (crossing.beyond, out);
+        } else {
+            (from, dir) = (crossing.inside, dir.reflected(-crossing.n));
+        }
+    }
+    None
+}
@@ -0,0 +1,88 @@
+use ultraviolet::Vec3;
+
+// Range of color temperatures covered by the fit in kelvin
+const MIN_KELVIN: f32 = 1667.;
+const MAX_KELVIN: f32 = 25000.;
+
+// Color of a black body at the given temperature in kelvin, such as 2700
+// for incandescent bulbs, 5500 for sunlight or 10000 for a blue sky. Scaled
+// so that its brightest component is 1.
+#[allow(clippy::excessive_precision)]
+pub fn kelvin(kelvin: f32) -> Vec3 {
+    // Kim et al., "Design of Advanced Color Temperature Control System for
+    // HDTV Applications" (2002), fitting the Planckian locus in CIE xy
+    let t = kelvin.clamp(MIN_KELVIN, MAX_KELVIN);
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000. {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222. {
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    } else if t <= 4000. {
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+    let rgb = xyy_to_rgb(x, y, 1.);
+    rgb / rgb.component_max()
+}
+
+// Parses "#rrggbb" or "rrggbb" as sRGB, converted to linear like the rest of
+// the renderer's colors
+pub fn hex(hex: &str) -> Option<Vec3> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if digits.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| {
+        let value = u8::from_str_radix(digits.get(i..i + 2)?, 16).ok()?;
+        Some(srgb_to_linear(value as f32 / 255.))
+    };
+    Some(Vec3::new(channel(0)?, channel(2)?, channel(4)?))
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Linear sRGB response to light of a single wavelength in nanometers, for
+// the CIE 1931 standard observer. Outside of the sRGB gamut, so some of its
+// components are negative, but sums over the spectrum are white.
+pub fn wavelength(nm: f32) -> Vec3 {
+    // Multi-lobe fit of the color matching functions by Wyman et al., "Simple
+    // Analytic Approximations to the CIE XYZ Color Matching Functions" (2013)
+    let lobe = |mean: f32, below: f32, above: f32| {
+        let t = (nm - mean) / if nm < mean { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    let x = 1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+        - 0.065 * lobe(501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8);
+    xyz_to_rgb(x, y, z)
+}
+
+// CIE xyY to linear sRGB, with colors outside of its gamut clipped
+pub(crate) fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vec3 {
+    if y <= 0. {
+        return Vec3::zero();
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1. - x - y) / y * luminance;
+    xyz_to_rgb(big_x, luminance, big_z).max_by_component(Vec3::zero())
+}
+
+fn xyz_to_rgb(x: f32, y: f32, z: f32) -> Vec3 {
+    Vec3::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
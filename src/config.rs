@@ -0,0 +1,106 @@
+use std::fs;
+use std::io::ErrorKind;
+
+use anyhow::{anyhow, bail, Result};
+
+// Settings in the config file use the names of the command line flags, with
+// underscores instead of dashes, and are applied before the command line
+pub const PATH: &str = "raytrace.toml";
+
+pub struct Entry {
+    pub line: usize,
+    pub key: String,
+    pub value: String,
+}
+
+impl Entry {
+    // The command line arguments with the same effect. Switches are set by
+    // true, and arrays become comma separated lists.
+    pub fn to_args(&self) -> Vec<String> {
+        let flag = format!("--{}", self.key.replace('_', "-"));
+        match self.value.as_str() {
+            "true" => vec![flag],
+            "false" => vec![],
+            _ => vec![flag, self.value.clone()],
+        }
+    }
+}
+
+// Reads the config file, if there is one
+pub fn load(path: &str) -> Result<Option<Vec<Entry>>> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse(&text)
+            .map(Some)
+            .map_err(|err| anyhow!("{}: {}", path, err)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(anyhow!("{}: {}", path, err)),
+    }
+}
+
+// Parses the subset of TOML needed for flat settings: key = value pairs
+// with strings, numbers, booleans and arrays of those, and comments
+pub fn parse(text: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| anyhow!("line {}: {}", i + 1, message);
+        if line.starts_with('[') {
+            return Err(error("tables are not supported"));
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected key = value"))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(error("invalid key"));
+        }
+        let value = value.trim();
+        let value = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Some(items) => items
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(scalar)
+                .collect::<Result<Vec<_>>>()
+                .map_err(|err| error(&err.to_string()))?
+                .join(","),
+            None => scalar(value).map_err(|err| error(&err.to_string()))?,
+        };
+        entries.push(Entry {
+            line: i + 1,
+            key: key.into(),
+            value,
+        });
+    }
+    Ok(entries)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// A string without its quotes, or a bare number or boolean as written
+fn scalar(value: &str) -> Result<String> {
+    if let Some(s) = value.strip_prefix('"') {
+        return match s.strip_suffix('"') {
+            Some(s) if !s.contains('"') => Ok(s.into()),
+            _ => bail!("invalid string: {}", value),
+        };
+    }
+    if value == "true" || value == "false" || value.parse::<f64>().is_ok() {
+        Ok(value.into())
+    } else {
+        bail!("invalid value: {}", value)
+    }
+}
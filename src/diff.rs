@@ -0,0 +1,96 @@
+use anyhow::{bail, Result};
+use image::{ImageBuffer, Rgb, Rgb32FImage, RgbImage, Rgba32FImage};
+
+// Differences of this size and above are white in the heat map
+const HEAT_SCALE: f32 = 0.25;
+// SSIM is computed over windows of this size, overlapping by half
+const SSIM_WINDOW: u32 = 8;
+// Stabilizing constants for SSIM, for values in the range 0 to 1
+const SSIM_C1: f32 = 0.01 * 0.01;
+const SSIM_C2: f32 = 0.03 * 0.03;
+
+pub struct Metrics {
+    // Root mean square error over all channels, including alpha
+    pub rmse: f32,
+    // Mean structural similarity of the luminance, 1 for identical images
+    pub ssim: f32,
+    // Largest difference in any channel
+    pub max: f32,
+}
+
+// Per pixel absolute difference, the largest over the channels
+pub fn absolute(a: &Rgba32FImage, b: &Rgba32FImage) -> Result<Rgb32FImage> {
+    if a.dimensions() != b.dimensions() {
+        bail!(
+            "images differ in size: {}x{} and {}x{}",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        );
+    }
+    let pixels = a
+        .pixels()
+        .zip(b.pixels())
+        .flat_map(|(a, b)| {
+            let d = (0..4).map(|i| (a[i] - b[i]).abs()).fold(0., f32::max);
+            [d; 3]
+        })
+        .collect();
+    Ok(ImageBuffer::from_raw(a.width(), a.height(), pixels).unwrap())
+}
+
+pub fn metrics(a: &Rgba32FImage, b: &Rgba32FImage, difference: &Rgb32FImage) -> Metrics {
+    let squared: f32 = a.iter().zip(b.iter()).map(|(a, b)| (a - b) * (a - b)).sum();
+    Metrics {
+        rmse: (squared / a.len() as f32).sqrt(),
+        ssim: ssim(&luminance(a), &luminance(b), a.width(), a.height()),
+        max: difference.pixels().map(|p| p[0]).fold(0., f32::max),
+    }
+}
+
+// Black through red and yellow to white as the difference grows
+pub fn heat_map(difference: &Rgb32FImage) -> RgbImage {
+    ImageBuffer::from_fn(difference.width(), difference.height(), |x, y| {
+        let t = (difference.get_pixel(x, y)[0] / HEAT_SCALE).clamp(0., 1.) * 3.;
+        let channel = |offset: f32| ((t - offset).clamp(0., 1.) * 255.) as u8;
+        Rgb([channel(0.), channel(1.), channel(2.)])
+    })
+}
+
+// Luminance of the colors over black, so that coverage counts too
+fn luminance(img: &Rgba32FImage) -> Vec<f32> {
+    img.pixels()
+        .map(|p| (0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2]) * p[3])
+        .collect()
+}
+
+fn ssim(a: &[f32], b: &[f32], width: u32, height: u32) -> f32 {
+    let window = SSIM_WINDOW.min(width).min(height);
+    let step = (window / 2).max(1);
+    let mut total = 0.;
+    let mut count = 0;
+    for y0 in (0..=height - window).step_by(step as usize) {
+        for x0 in (0..=width - window).step_by(step as usize) {
+            let indices = (y0..y0 + window)
+                .flat_map(|y| (x0..x0 + window).map(move |x| (y * width + x) as usize));
+            let n = (window * window) as f32;
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0., 0., 0., 0., 0.);
+            for i in indices {
+                sa += a[i];
+                sb += b[i];
+                saa += a[i] * a[i];
+                sbb += b[i] * b[i];
+                sab += a[i] * b[i];
+            }
+            let (mean_a, mean_b) = (sa / n, sb / n);
+            let var_a = saa / n - mean_a * mean_a;
+            let var_b = sbb / n - mean_b * mean_b;
+            let covariance = sab / n - mean_a * mean_b;
+            total += (2. * mean_a * mean_b + SSIM_C1) * (2. * covariance + SSIM_C2)
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            count += 1;
+        }
+    }
+    total / count as f32
+}
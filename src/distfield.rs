@@ -1,18 +1,395 @@
-use ultraviolet::Vec3;
+use ultraviolet::{Lerp, Vec3};
 
-#[derive(Clone, Copy)]
+use crate::bytecode::Op;
+use crate::json::Json;
+use crate::validate::{check_ops, Warning};
+
+// Light scattered through the inside of a translucent material, such as wax
+// or jade. Depth is the distance over which it falls off to about a third.
+#[derive(Clone, Copy, Debug)]
+pub struct Scatter {
+    pub color: Vec3,
+    pub depth: f32,
+}
+
+// Light absorbed on its way through a refractive object, leaving this color
+// of it after each depth traveled inside, so thick parts are tinted more
+// deeply than thin ones (the Beer-Lambert law)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Absorption {
+    pub color: Vec3,
+    pub depth: f32,
+}
+
+impl Absorption {
+    // Fraction of the light left after the distance
+    pub fn transmittance(&self, distance: f32) -> Vec3 {
+        let k = distance / self.depth;
+        Vec3::new(
+            self.color.x.powf(k),
+            self.color.y.powf(k),
+            self.color.z.powf(k),
+        )
+    }
+}
+
+// Fake surface detail from noise, applied to the shading normal only. Scale
+// is the size of the features, and strength how steep they appear.
+#[derive(Clone, Copy, Debug)]
+pub struct Bump {
+    pub scale: f32,
+    pub strength: f32,
+}
+
+// Wear on edges and dirt in cavities, blending the color towards edge where
+// the surface curves outwards and towards cavity where it curves inwards, at
+// the scale of the radius. See Scene::curvature.
+#[derive(Clone, Copy, Debug)]
+pub struct Wear {
+    pub radius: f32,
+    pub edge: Vec3,
+    pub cavity: Vec3,
+}
+
+// Which kinds of rays see a surface. Rays pass through it where it's hidden,
+// so it can for instance block light without being seen itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadow: bool,
+    pub reflection: bool,
+}
+
+impl Visibility {
+    pub const ALL: Self = Self {
+        camera: true,
+        shadow: true,
+        reflection: true,
+    };
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+// Identifies an object for picking, see pick
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectId(pub u32);
+
+#[derive(Clone, Copy, Debug)]
 pub struct Surface {
     pub color: Vec3,
     pub reflectivity: f32,
+    pub roughness: f32,
+    // Light given off by the surface itself, added to its shading
+    pub emission: Vec3,
+    // Index of refraction of clear objects that light passes through, such
+    // as 1.5 for glass, which replaces their diffuse shading
+    pub ior: Option<f32>,
+    // Cauchy's B coefficient in square micrometers, by which the index of
+    // refraction varies with the wavelength, see Surface::with_dispersion
+    pub dispersion: f32,
+    pub absorption: Option<Absorption>,
+    pub light_mask: u32,
+    pub scatter: Option<Scatter>,
+    pub bump: Option<Bump>,
+    pub wear: Option<Wear>,
+    // Number of flat bands to cut diffuse lighting into, see Toon
+    pub toon: Option<u32>,
+    pub object: Option<ObjectId>,
+    pub visibility: Visibility,
+    pub shadow_catcher: bool,
+    pub two_sided: bool,
 }
 
 impl Surface {
-    fn new(color: Vec3, reflectivity: f32) -> Self {
+    pub fn new(color: Vec3, reflectivity: f32) -> Self {
         Self {
             color,
             reflectivity,
+            roughness: 0.,
+            emission: Vec3::zero(),
+            ior: None,
+            dispersion: 0.,
+            absorption: None,
+            light_mask: u32::MAX,
+            scatter: None,
+            bump: None,
+            wear: None,
+            toon: None,
+            object: None,
+            visibility: Visibility::ALL,
+            shadow_catcher: false,
+            two_sided: false,
+        }
+    }
+
+    // Shows only the shadows cast onto it, as black with the amount of shadow
+    // for alpha, so objects can be composited onto a photo of the ground.
+    // It's hidden from reflections and casts no shadows itself.
+    pub fn shadow_catcher() -> Self {
+        Self {
+            visibility: Visibility {
+                camera: true,
+                shadow: false,
+                reflection: false,
+            },
+            shadow_catcher: true,
+            ..Self::new(Vec3::zero(), 0.)
+        }
+    }
+
+    // Blurs reflections, from 0 for a perfect mirror up to 1
+    pub fn with_roughness(self, roughness: f32) -> Self {
+        Self { roughness, ..self }
+    }
+
+    pub fn with_emission(self, emission: Vec3) -> Self {
+        Self { emission, ..self }
+    }
+
+    // Makes the object clear, bending light that passes through it by the
+    // index of refraction. Reflects more towards grazing angles, on top of
+    // the reflectivity.
+    pub fn with_refraction(self, ior: f32) -> Self {
+        Self {
+            ior: Some(ior),
+            ..self
+        }
+    }
+
+    // Splits light passing through a refractive object into its colors, when
+    // rendering with Settings::dispersion. The index of refraction is taken
+    // at the sodium D line of 589.3nm, and varies around it by Cauchy's
+    // equation with this B coefficient, such as 0.0042 for crown glass.
+    pub fn with_dispersion(self, dispersion: f32) -> Self {
+        Self { dispersion, ..self }
+    }
+
+    // Index of refraction for light of the wavelength in nanometers, or
+    // without one at the sodium D line
+    pub fn refractive_index(&self, wavelength: Option<f32>) -> Option<f32> {
+        let ior = self.ior?;
+        match wavelength {
+            Some(nm) => {
+                let (um, d_line) = (nm / 1000., 0.5893);
+                Some(ior + self.dispersion * (1. / (um * um) - 1. / (d_line * d_line)))
+            }
+            None => Some(ior),
+        }
+    }
+
+    // Tints light passing through a refractive object, see Absorption
+    pub fn with_absorption(self, color: Vec3, depth: f32) -> Self {
+        Self {
+            absorption: Some(Absorption { color, depth }),
+            ..self
+        }
+    }
+
+    // Only lights in these groups will light the surface, see LightGroups
+    pub fn with_light_mask(self, light_mask: u32) -> Self {
+        Self { light_mask, ..self }
+    }
+
+    pub fn with_scatter(self, color: Vec3, depth: f32) -> Self {
+        Self {
+            scatter: Some(Scatter { color, depth }),
+            ..self
         }
     }
+
+    pub fn with_bump(self, scale: f32, strength: f32) -> Self {
+        Self {
+            bump: Some(Bump { scale, strength }),
+            ..self
+        }
+    }
+
+    pub fn with_wear(self, radius: f32, edge: Vec3, cavity: Vec3) -> Self {
+        Self {
+            wear: Some(Wear {
+                radius,
+                edge,
+                cavity,
+            }),
+            ..self
+        }
+    }
+
+    pub fn with_toon(self, bands: u32) -> Self {
+        Self {
+            toon: Some(bands),
+            ..self
+        }
+    }
+
+    pub fn with_visibility(self, visibility: Visibility) -> Self {
+        Self { visibility, ..self }
+    }
+
+    // Whether the inside of the surface is shaded when seen from within the
+    // object, rather than looked through
+    pub fn with_two_sided(self, two_sided: bool) -> Self {
+        Self { two_sided, ..self }
+    }
+
+    pub fn with_object(self, id: u32) -> Self {
+        Self {
+            object: Some(ObjectId(id)),
+            ..self
+        }
+    }
+
+    // Blends the color, reflectivity, roughness and emission towards the
+    // other surface by t, for varying them over an object with a procedural
+    // mask. Everything else is taken from the nearer of the two.
+    pub fn mix(self, other: Surface, t: f32) -> Self {
+        let base = if t < 0.5 { self } else { other };
+        Self {
+            color: self.color.lerp(other.color, t),
+            reflectivity: self.reflectivity.lerp(other.reflectivity, t),
+            roughness: self.roughness.lerp(other.roughness, t),
+            emission: self.emission.lerp(other.emission, t),
+            ..base
+        }
+    }
+
+    // As saved in scene files. When loading, anything left out takes its
+    // value from Surface::new with a white color.
+    pub(crate) fn to_json(self) -> Json {
+        let pair = |color: Vec3, key: &str, value: f32| {
+            Json::object([("color", color.into()), (key, value.into())])
+        };
+        Json::object([
+            ("color", self.color.into()),
+            ("reflectivity", self.reflectivity.into()),
+            ("roughness", self.roughness.into()),
+            ("emission", self.emission.into()),
+            ("ior", self.ior.into()),
+            ("dispersion", self.dispersion.into()),
+            (
+                "absorption",
+                self.absorption
+                    .map(|a| pair(a.color, "depth", a.depth))
+                    .into(),
+            ),
+            ("light_mask", self.light_mask.into()),
+            (
+                "scatter",
+                self.scatter.map(|s| pair(s.color, "depth", s.depth)).into(),
+            ),
+            (
+                "bump",
+                self.bump
+                    .map(|b| {
+                        Json::object([("scale", b.scale.into()), ("strength", b.strength.into())])
+                    })
+                    .into(),
+            ),
+            (
+                "wear",
+                self.wear
+                    .map(|w| {
+                        Json::object([
+                            ("radius", w.radius.into()),
+                            ("edge", w.edge.into()),
+                            ("cavity", w.cavity.into()),
+                        ])
+                    })
+                    .into(),
+            ),
+            ("toon", self.toon.into()),
+            ("object", self.object.map(|id| id.0).into()),
+            (
+                "visibility",
+                Json::object([
+                    ("camera", self.visibility.camera.into()),
+                    ("shadow", self.visibility.shadow.into()),
+                    ("reflection", self.visibility.reflection.into()),
+                ]),
+            ),
+            ("shadow_catcher", self.shadow_catcher.into()),
+            ("two_sided", self.two_sided.into()),
+        ])
+    }
+
+    pub(crate) fn from_json(json: &Json) -> Result<Self, String> {
+        let defaults = Self::new(Vec3::one(), 0.);
+        let pair = |json: &Json, key| -> Result<(Vec3, f32), String> {
+            Ok((
+                json.required("color", Json::vec3)?,
+                json.required(key, Json::f32)?,
+            ))
+        };
+        let absorption = match json.optional("absorption", Some)? {
+            Some(a) => pair(a, "depth").map(|(color, depth)| Some(Absorption { color, depth }))?,
+            None => None,
+        };
+        let scatter = match json.optional("scatter", Some)? {
+            Some(s) => pair(s, "depth").map(|(color, depth)| Some(Scatter { color, depth }))?,
+            None => None,
+        };
+        let bump = match json.optional("bump", Some)? {
+            Some(b) => Some(Bump {
+                scale: b.required("scale", Json::f32)?,
+                strength: b.required("strength", Json::f32)?,
+            }),
+            None => None,
+        };
+        let wear = match json.optional("wear", Some)? {
+            Some(w) => Some(Wear {
+                radius: w.required("radius", Json::f32)?,
+                edge: w.required("edge", Json::vec3)?,
+                cavity: w.required("cavity", Json::vec3)?,
+            }),
+            None => None,
+        };
+        let visibility = match json.optional("visibility", Some)? {
+            Some(v) => Visibility {
+                camera: v.optional("camera", Json::bool)?.unwrap_or(true),
+                shadow: v.optional("shadow", Json::bool)?.unwrap_or(true),
+                reflection: v.optional("reflection", Json::bool)?.unwrap_or(true),
+            },
+            None => defaults.visibility,
+        };
+        Ok(Self {
+            color: json
+                .optional("color", Json::vec3)?
+                .unwrap_or(defaults.color),
+            reflectivity: json
+                .optional("reflectivity", Json::f32)?
+                .unwrap_or(defaults.reflectivity),
+            roughness: json
+                .optional("roughness", Json::f32)?
+                .unwrap_or(defaults.roughness),
+            emission: json
+                .optional("emission", Json::vec3)?
+                .unwrap_or(defaults.emission),
+            ior: json.optional("ior", Json::f32)?,
+            dispersion: json
+                .optional("dispersion", Json::f32)?
+                .unwrap_or(defaults.dispersion),
+            absorption,
+            light_mask: json
+                .optional("light_mask", Json::u32)?
+                .unwrap_or(defaults.light_mask),
+            scatter,
+            bump,
+            wear,
+            toon: json.optional("toon", Json::u32)?,
+            object: json.optional("object", Json::u32)?.map(ObjectId),
+            visibility,
+            shadow_catcher: json
+                .optional("shadow_catcher", Json::bool)?
+                .unwrap_or(defaults.shadow_catcher),
+            two_sided: json
+                .optional("two_sided", Json::bool)?
+                .unwrap_or(defaults.two_sided),
+        })
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -21,7 +398,49 @@ pub struct Sample {
     pub surface: Surface,
 }
 
-fn union(s1: Sample, s2: Sample) -> Sample {
+pub trait Sdf: Send + Sync {
+    fn sample(&self, p: Vec3) -> Sample;
+
+    // Appends instructions that evaluate this field, or returns None if it
+    // can only be sampled directly (such as plain functions)
+    fn compile(&self, _ops: &mut Vec<Op>) -> Option<()> {
+        None
+    }
+
+    // Adds warnings about likely authoring mistakes, see Scene::validate.
+    // Fields that can be compiled are checked by their instructions.
+    fn validate(&self, warnings: &mut Vec<Warning>) {
+        let mut ops = Vec::new();
+        if self.compile(&mut ops).is_some() {
+            check_ops(warnings, &ops);
+        }
+    }
+}
+
+impl Sdf for Box<dyn Sdf> {
+    fn sample(&self, p: Vec3) -> Sample {
+        (**self).sample(p)
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        (**self).compile(ops)
+    }
+
+    fn validate(&self, warnings: &mut Vec<Warning>) {
+        (**self).validate(warnings)
+    }
+}
+
+impl<F> Sdf for F
+where
+    F: Fn(Vec3) -> Sample + Send + Sync,
+{
+    fn sample(&self, p: Vec3) -> Sample {
+        self(p)
+    }
+}
+
+pub fn union(s1: Sample, s2: Sample) -> Sample {
     if s1.distance < s2.distance {
         s1
     } else {
@@ -29,7 +448,7 @@ fn union(s1: Sample, s2: Sample) -> Sample {
     }
 }
 
-fn intersect(s1: Sample, s2: Sample) -> Sample {
+pub fn intersect(s1: Sample, s2: Sample) -> Sample {
     if s1.distance < s2.distance {
         s2
     } else {
@@ -37,14 +456,14 @@ fn intersect(s1: Sample, s2: Sample) -> Sample {
     }
 }
 
-fn invert(s: Sample) -> Sample {
+pub fn invert(s: Sample) -> Sample {
     Sample {
-        distance: -1.0 * s.distance,
+        distance: -s.distance,
         surface: s.surface,
     }
 }
 
-fn sphere(p: Vec3, center: Vec3, radius: f32, surface: Surface) -> Sample {
+pub fn sphere(p: Vec3, center: Vec3, radius: f32, surface: Surface) -> Sample {
     // sphere at origin
     Sample {
         distance: (p - center).mag() - radius,
@@ -52,19 +471,71 @@ fn sphere(p: Vec3, center: Vec3, radius: f32, surface: Surface) -> Sample {
     }
 }
 
-fn warp(p: Vec3) -> Vec3 {
+// A cylinder from a to b with rounded ends
+pub fn capsule(p: Vec3, a: Vec3, b: Vec3, radius: f32, surface: Surface) -> Sample {
+    let ab = b - a;
+    let t = if ab.mag_sq() > 0. {
+        ((p - a).dot(ab) / ab.mag_sq()).clamp(0., 1.)
+    } else {
+        0.
+    };
+    Sample {
+        distance: (p - a - ab * t).mag() - radius,
+        surface,
+    }
+}
+
+pub fn warp(p: Vec3) -> Vec3 {
     p + Vec3::new((0.4 * p.y).sin(), (0.6 * p.z).sin(), (0.8 * p.x).sin())
 }
 
-fn displace(p: Vec3, scale: f32, detail: f32, s: Sample) -> Sample {
+pub fn displacement(p: Vec3, scale: f32, detail: f32) -> f32 {
     let p = p * detail;
-    let displacement = scale * p.x.sin() * p.y.sin() * p.z.sin();
+    scale * p.x.sin() * p.y.sin() * p.z.sin()
+}
+
+pub fn displace(p: Vec3, scale: f32, detail: f32, s: Sample) -> Sample {
     Sample {
-        distance: s.distance + displacement,
+        distance: s.distance + displacement(p, scale, detail),
         ..s
     }
 }
 
+pub fn mandelbulb(p: Vec3, center: Vec3, scale: f32, power: f32, surface: Surface) -> Sample {
+    // distance estimator, evaluated in unit space around the center
+    let p = (p - center) / scale;
+    if p.mag() > 2.0 {
+        // the estimator overshoots far away, use a bounding sphere instead
+        return Sample {
+            distance: (p.mag() - 1.5) * scale,
+            surface,
+        };
+    }
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+    for _ in 0..8 {
+        r = z.mag();
+        if r > 2.0 {
+            break;
+        }
+        let theta = (z.z / r).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z = Vec3::new(
+            theta.sin() * phi.cos(),
+            phi.sin() * theta.sin(),
+            theta.cos(),
+        ) * zr
+            + p;
+    }
+    Sample {
+        distance: 0.5 * r.ln() * r / dr * scale,
+        surface,
+    }
+}
+
 pub fn distfield(p: Vec3) -> Sample {
     let mat1 = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.4);
     let mat2 = Surface::new(Vec3::new(0.4, 0.8, 1.0), 0.2);
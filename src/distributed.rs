@@ -0,0 +1,245 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use image::{ImageBuffer, RgbaImage};
+use rayon::prelude::*;
+
+use raycast::{render_pixel, scenes, Scene, Settings};
+
+const TILE_SIZE: u32 = 64;
+// Workers that take longer than this to send back a tile are dropped, and
+// the tile is given to another
+const TILE_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Copy)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+struct Job {
+    pending: Vec<Tile>,
+    remaining: usize,
+    img: RgbaImage,
+}
+
+fn tiles(width: u32, height: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(TILE_SIZE as usize) {
+        for x in (0..width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: TILE_SIZE.min(width - x),
+                height: TILE_SIZE.min(height - y),
+            });
+        }
+    }
+    // Workers pop from the back, so hand out tiles top to bottom
+    tiles.reverse();
+    tiles
+}
+
+fn assign(
+    stream: &mut TcpStream,
+    job: &(Mutex<Job>, Condvar),
+    scene: &str,
+    settings: &Settings,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    stream.set_read_timeout(Some(TILE_TIMEOUT))?;
+    let (lock, done) = job;
+    loop {
+        // Workers without a tile stay connected until the others have
+        // finished, to take over tiles from any that fail
+        let tile = {
+            let mut job = lock.lock().unwrap();
+            loop {
+                if let Some(tile) = job.pending.pop() {
+                    break Some(tile);
+                }
+                if job.remaining == 0 {
+                    break None;
+                }
+                job = done.wait(job).unwrap();
+            }
+        };
+        let tile = match tile {
+            Some(tile) => tile,
+            None => {
+                stream.write_all(b"DONE\n")?;
+                return Ok(());
+            }
+        };
+
+        let mut pixels = vec![0u8; (tile.width * tile.height * 4) as usize];
+        let result = writeln!(
+            stream,
+            "TILE {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {}",
+            scene,
+            settings.lens,
+            settings
+                .stereo
+                .map_or_else(|| "mono".to_string(), |stereo| stereo.to_string()),
+            settings
+                .reflection_offset
+                .map_or_else(|| "march".to_string(), |offset| offset.to_string()),
+            settings
+                .toon
+                .map_or_else(|| "smooth".to_string(), |toon| toon.to_string()),
+            settings
+                .dispersion
+                .map_or_else(|| "rgb".to_string(), |count| count.to_string()),
+            width,
+            height,
+            tile.x,
+            tile.y,
+            tile.width,
+            tile.height,
+            settings.samples,
+            settings.seed,
+            settings.max_bounces,
+            settings.roulette as u8,
+        )
+        .and_then(|_| stream.read_exact(&mut pixels));
+        let mut job = lock.lock().unwrap();
+        if let Err(err) = result {
+            // Give the tile to the next worker that asks for one
+            job.pending.push(tile);
+            done.notify_all();
+            return Err(err.into());
+        }
+        let tile_img: RgbaImage = ImageBuffer::from_raw(tile.width, tile.height, pixels).unwrap();
+        for (x, y, pixel) in tile_img.enumerate_pixels() {
+            job.img.put_pixel(tile.x + x, tile.y + y, *pixel);
+        }
+        job.remaining -= 1;
+        done.notify_all();
+    }
+}
+
+pub fn coordinate(
+    addr: &str,
+    scene: &str,
+    settings: &Settings,
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage> {
+    let listener = TcpListener::bind(addr).map_err(|err| anyhow!("{}: {}", addr, err))?;
+    println!("waiting for workers on {}", listener.local_addr()?);
+
+    let pending = tiles(width, height);
+    let job = Arc::new((
+        Mutex::new(Job {
+            remaining: pending.len(),
+            pending,
+            img: ImageBuffer::new(width, height),
+        }),
+        Condvar::new(),
+    ));
+
+    {
+        let job = job.clone();
+        let scene = scene.to_string();
+        let settings = *settings;
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let job = job.clone();
+                let scene = scene.clone();
+                thread::spawn(move || {
+                    let peer = stream.peer_addr().map(|addr| addr.to_string());
+                    let peer = peer.unwrap_or_default();
+                    println!("worker {} connected", peer);
+                    if let Err(err) = assign(&mut stream, &job, &scene, &settings, width, height) {
+                        eprintln!("worker {} failed: {}", peer, err);
+                    }
+                });
+            }
+        });
+    }
+
+    let (lock, done) = &*job;
+    let mut job = lock.lock().unwrap();
+    while job.remaining > 0 {
+        job = done.wait(job).unwrap();
+    }
+    Ok(std::mem::take(&mut job.img))
+}
+
+fn render_tile(scene: &Scene, settings: &Settings, width: u32, height: u32, tile: Tile) -> Vec<u8> {
+    let coords: Vec<_> = (tile.y..tile.y + tile.height)
+        .flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+        .collect();
+    coords
+        .par_iter()
+        .flat_map_iter(|&(x, y)| render_pixel(scene, settings, width, height, x, y))
+        .collect()
+}
+
+pub fn work(addr: &str) -> Result<()> {
+    let mut stream = TcpStream::connect(addr).map_err(|err| anyhow!("{}: {}", addr, err))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut scene: Option<(String, Scene)> = None;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let fields: Vec<_> = line.split_whitespace().collect();
+        match fields[..] {
+            ["DONE"] => return Ok(()),
+            ["TILE", name, lens, stereo, reflection, toon, dispersion, ref numbers @ ..]
+                if numbers.len() == 10 =>
+            {
+                let numbers = numbers
+                    .iter()
+                    .map(|n| n.parse())
+                    .collect::<Result<Vec<u64>, _>>()?;
+                let settings = Settings {
+                    samples: numbers[6] as u32,
+                    seed: numbers[7],
+                    max_bounces: numbers[8] as usize,
+                    roulette: numbers[9] != 0,
+                    lens: lens.parse().map_err(|err| anyhow!("{}", err))?,
+                    stereo: match stereo {
+                        "mono" => None,
+                        stereo => Some(stereo.parse().map_err(|err| anyhow!("{}", err))?),
+                    },
+                    reflection_offset: match reflection {
+                        "march" => None,
+                        offset => Some(offset.parse()?),
+                    },
+                    toon: match toon {
+                        "smooth" => None,
+                        toon => Some(toon.parse().map_err(|err| anyhow!("{}", err))?),
+                    },
+                    dispersion: match dispersion {
+                        "rgb" => None,
+                        count => Some(count.parse()?),
+                    },
+                };
+                let numbers: Vec<u32> = numbers.iter().map(|&n| n as u32).collect();
+                let tile = Tile {
+                    x: numbers[2],
+                    y: numbers[3],
+                    width: numbers[4],
+                    height: numbers[5],
+                };
+                if scene.as_ref().map(|(current, _)| current.as_str()) != Some(name) {
+                    let loaded =
+                        scenes::by_name(name).ok_or_else(|| anyhow!("unknown scene: {}", name))?;
+                    scene = Some((name.to_string(), loaded));
+                }
+                let (_, scene) = scene.as_ref().unwrap();
+                stream.write_all(&render_tile(scene, &settings, numbers[0], numbers[1], tile))?;
+                println!("rendered tile at {}, {}", tile.x, tile.y);
+            }
+            _ => bail!("unexpected message from coordinator: {:?}", line),
+        }
+    }
+}
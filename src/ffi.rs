@@ -0,0 +1,303 @@
+// C interface to rendering and ray queries, for driving the renderer from
+// other languages through the shared library. The declarations are in
+// include/raycast.h. Scenes are given as the text of a scene file, see
+// Scene::save. Functions that can fail return one of the negative error
+// codes, with a message from last_error_message. Pointers must be valid for
+// what they point to, points and directions are arrays of three floats,
+// and strings are nul terminated UTF-8. Panics are caught rather than
+// unwinding into the caller, and reported as ERROR_PANIC.
+#![allow(clippy::missing_safety_doc)]
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use ultraviolet::Vec3;
+
+use crate::{query, render_rgba, Scene, Settings};
+
+pub const ERROR_NULL_POINTER: c_int = -1;
+pub const ERROR_INVALID_ARGUMENT: c_int = -2;
+pub const ERROR_INVALID_SCENE: c_int = -3;
+pub const ERROR_PANIC: c_int = -4;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub seed: u64,
+    pub max_bounces: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RayHit {
+    pub p: [f32; 3],
+    pub n: [f32; 3],
+    // Distance along the ray from where it started
+    pub distance: f32,
+    pub color: [f32; 3],
+    // Id of the object that was hit, or -1 for surfaces without one
+    pub object: i64,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(code: c_int, message: impl Into<String>) -> c_int {
+    // Messages come from our own errors, which have no nul bytes
+    let message = CString::new(message.into()).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = message);
+    code
+}
+
+// Runs the body of an exported function, returning the error value if it
+// panics, with the panic's message as the last error
+fn guard<T>(error: T, body: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(payload) => {
+            fail(ERROR_PANIC, panic_message(&*payload));
+            error
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    let message = match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message.as_str(),
+        _ => "unknown error",
+    };
+    format!("panicked: {}", message)
+}
+
+// Message for the last error on this thread, valid until the next call that
+// fails on it
+#[no_mangle]
+pub extern "C" fn last_error_message() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|error| error.borrow().as_ptr())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn render_options_default() -> RenderOptions {
+    let settings = Settings::default();
+    RenderOptions {
+        width: 640,
+        height: 480,
+        samples: settings.samples,
+        seed: settings.seed,
+        max_bounces: settings.max_bounces as u32,
+    }
+}
+
+// Parses a scene file, returning null if it isn't valid. Free it with
+// scene_free.
+#[no_mangle]
+pub unsafe extern "C" fn scene_from_json(scene_json: *const c_char) -> *mut Scene {
+    guard(ptr::null_mut(), || match parse_scene(scene_json) {
+        Ok(scene) => Box::into_raw(Box::new(scene)),
+        Err(_) => ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn scene_free(scene: *mut Scene) {
+    guard((), || {
+        if !scene.is_null() {
+            drop(Box::from_raw(scene));
+        }
+    })
+}
+
+// Renders the scene into the buffer as 8 bit RGBA, row by row from the top,
+// which must hold width * height * 4 bytes
+#[no_mangle]
+pub unsafe extern "C" fn render_scene(
+    scene_json: *const c_char,
+    options: *const RenderOptions,
+    out_buffer: *mut u8,
+) -> c_int {
+    guard(ERROR_PANIC, || match parse_scene(scene_json) {
+        Ok(scene) => scene_render(&scene, options, out_buffer),
+        Err(code) => code,
+    })
+}
+
+// As render_scene, for a scene that was already parsed
+#[no_mangle]
+pub unsafe extern "C" fn scene_render(
+    scene: *const Scene,
+    options: *const RenderOptions,
+    out_buffer: *mut u8,
+) -> c_int {
+    guard(ERROR_PANIC, || {
+        let (scene, options) = match (scene.as_ref(), options.as_ref()) {
+            (Some(scene), Some(options)) if !out_buffer.is_null() => (scene, options),
+            _ => return fail(ERROR_NULL_POINTER, "null pointer"),
+        };
+        if options.width == 0 || options.height == 0 || options.samples == 0 {
+            return fail(
+                ERROR_INVALID_ARGUMENT,
+                "width, height and samples must be at least 1",
+            );
+        }
+        // Pixels are numbered with 32 bits
+        if options.width as u64 * options.height as u64 > u32::MAX as u64 {
+            return fail(ERROR_INVALID_ARGUMENT, "too many pixels");
+        }
+        let settings = Settings {
+            samples: options.samples,
+            seed: options.seed,
+            max_bounces: options.max_bounces as usize,
+            ..Settings::default()
+        };
+        let pixels = render_rgba(scene, &settings, options.width, options.height);
+        ptr::copy_nonoverlapping(pixels.as_ptr(), out_buffer, pixels.len());
+        0
+    })
+}
+
+// Casts a ray from the point along the direction, which doesn't need to be
+// normalized. Returns 1 and fills in the hit if it meets a surface, and 0
+// if it doesn't.
+#[no_mangle]
+pub unsafe extern "C" fn scene_cast_ray(
+    scene: *const Scene,
+    from: *const f32,
+    dir: *const f32,
+    hit: *mut RayHit,
+) -> c_int {
+    guard(ERROR_PANIC, || {
+        let (scene, from, dir) = match ray_arguments(scene, from, dir) {
+            Ok(arguments) => arguments,
+            Err(code) => return code,
+        };
+        if hit.is_null() {
+            return fail(ERROR_NULL_POINTER, "null pointer");
+        }
+        match query::cast(scene, from, dir) {
+            Some(found) => {
+                *hit = RayHit {
+                    p: *found.p.as_array(),
+                    n: *found.n.as_array(),
+                    distance: found.distance,
+                    color: *found.surface.color.as_array(),
+                    object: found.surface.object.map_or(-1, |id| id.0 as i64),
+                };
+                1
+            }
+            None => 0,
+        }
+    })
+}
+
+// Number of steps the ray takes until it meets a surface or gives up
+#[no_mangle]
+pub unsafe extern "C" fn scene_ray_steps(
+    scene: *const Scene,
+    from: *const f32,
+    dir: *const f32,
+) -> i64 {
+    guard(ERROR_PANIC as i64, || {
+        match ray_arguments(scene, from, dir) {
+            Ok((scene, from, dir)) => query::steps(scene, from, dir) as i64,
+            Err(code) => code as i64,
+        }
+    })
+}
+
+// Where the ray first leaves the object it starts in, or where it starts
+// if that is outside. Returns 1 and fills in the point if it does, and 0 if
+// it doesn't leave within the scene.
+#[no_mangle]
+pub unsafe extern "C" fn scene_leave(
+    scene: *const Scene,
+    from: *const f32,
+    dir: *const f32,
+    out: *mut f32,
+) -> c_int {
+    guard(ERROR_PANIC, || {
+        let (scene, from, dir) = match ray_arguments(scene, from, dir) {
+            Ok(arguments) => arguments,
+            Err(code) => return code,
+        };
+        if out.is_null() {
+            return fail(ERROR_NULL_POINTER, "null pointer");
+        }
+        match query::leave(scene, from, dir) {
+            Some(p) => {
+                ptr::copy_nonoverlapping(p.as_ptr(), out, 3);
+                1
+            }
+            None => 0,
+        }
+    })
+}
+
+// Normal of the surface at the point, estimated over the precision rays
+// have after traveling the distance
+#[no_mangle]
+pub unsafe extern "C" fn scene_normal(
+    scene: *const Scene,
+    p: *const f32,
+    distance: f32,
+    out: *mut f32,
+) -> c_int {
+    guard(ERROR_PANIC, || {
+        let (scene, p) = match (scene.as_ref(), read_vec3(p)) {
+            (Some(scene), Some(p)) if !out.is_null() => (scene, p),
+            _ => return fail(ERROR_NULL_POINTER, "null pointer"),
+        };
+        let n = query::normal(scene, p, distance);
+        ptr::copy_nonoverlapping(n.as_ptr(), out, 3);
+        0
+    })
+}
+
+unsafe fn parse_scene(scene_json: *const c_char) -> Result<Scene, c_int> {
+    if scene_json.is_null() {
+        return Err(fail(ERROR_NULL_POINTER, "scene json is null"));
+    }
+    let text = CStr::from_ptr(scene_json)
+        .to_str()
+        .map_err(|_| fail(ERROR_INVALID_SCENE, "scene json is not UTF-8"))?;
+    Scene::parse(text).map_err(|err| fail(ERROR_INVALID_SCENE, err))
+}
+
+unsafe fn read_vec3(p: *const f32) -> Option<Vec3> {
+    if p.is_null() {
+        return None;
+    }
+    let [x, y, z] = ptr::read_unaligned(p as *const [f32; 3]);
+    Some(Vec3::new(x, y, z))
+}
+
+unsafe fn ray_arguments<'a>(
+    scene: *const Scene,
+    from: *const f32,
+    dir: *const f32,
+) -> Result<(&'a Scene, Vec3, Vec3), c_int> {
+    let (scene, from, dir) = match (scene.as_ref(), read_vec3(from), read_vec3(dir)) {
+        (Some(scene), Some(from), Some(dir)) => (scene, from, dir),
+        _ => return Err(fail(ERROR_NULL_POINTER, "null pointer")),
+    };
+    let length = dir.mag();
+    if !(length > 0. && length.is_finite() && from.as_array().iter().all(|x| x.is_finite())) {
+        return Err(fail(
+            ERROR_INVALID_ARGUMENT,
+            "rays need a finite start and a direction",
+        ));
+    }
+    Ok((scene, from, dir / length))
+}
@@ -0,0 +1,230 @@
+use ultraviolet::{Vec2, Vec3};
+
+// A built-in stroke font for text in scenes, see graph::Text. Glyphs are
+// polylines on a grid 4 units wide and 6 tall, from the baseline up to the
+// top of the capitals. Lowercase letters are drawn as capitals.
+
+pub type Glyph = &'static [&'static [(i8, i8)]];
+
+const GLYPH_WIDTH: f32 = 4.;
+const GLYPH_HEIGHT: f32 = 6.;
+// Distance from one glyph to the next, in grid units
+const ADVANCE: f32 = 6.;
+// Radius of the strokes, in grid units
+const STROKE: f32 = 0.45;
+
+const O: &[(i8, i8)] = &[
+    (1, 0),
+    (0, 1),
+    (0, 5),
+    (1, 6),
+    (3, 6),
+    (4, 5),
+    (4, 1),
+    (3, 0),
+    (1, 0),
+];
+const P: &[(i8, i8)] = &[(0, 0), (0, 6), (3, 6), (4, 5), (4, 4), (3, 3), (0, 3)];
+
+pub fn glyph(c: char) -> Glyph {
+    match c.to_ascii_uppercase() {
+        'A' => &[&[(0, 0), (0, 4), (2, 6), (4, 4), (4, 0)], &[(0, 3), (4, 3)]],
+        'B' => &[
+            &[(0, 0), (0, 6), (3, 6), (4, 5), (4, 4), (3, 3), (0, 3)],
+            &[(3, 3), (4, 2), (4, 1), (3, 0), (0, 0)],
+        ],
+        'C' => &[&[
+            (4, 5),
+            (3, 6),
+            (1, 6),
+            (0, 5),
+            (0, 1),
+            (1, 0),
+            (3, 0),
+            (4, 1),
+        ]],
+        'D' => &[&[(0, 0), (0, 6), (2, 6), (4, 4), (4, 2), (2, 0), (0, 0)]],
+        'E' => &[&[(4, 6), (0, 6), (0, 0), (4, 0)], &[(0, 3), (3, 3)]],
+        'F' => &[&[(4, 6), (0, 6), (0, 0)], &[(0, 3), (3, 3)]],
+        'G' => &[&[
+            (4, 5),
+            (3, 6),
+            (1, 6),
+            (0, 5),
+            (0, 1),
+            (1, 0),
+            (3, 0),
+            (4, 1),
+            (4, 3),
+            (2, 3),
+        ]],
+        'H' => &[&[(0, 0), (0, 6)], &[(4, 0), (4, 6)], &[(0, 3), (4, 3)]],
+        'I' => &[&[(1, 6), (3, 6)], &[(2, 6), (2, 0)], &[(1, 0), (3, 0)]],
+        'J' => &[&[(4, 6), (4, 1), (3, 0), (1, 0), (0, 1)]],
+        'K' => &[&[(0, 0), (0, 6)], &[(4, 6), (0, 2)], &[(1, 3), (4, 0)]],
+        'L' => &[&[(0, 6), (0, 0), (4, 0)]],
+        'M' => &[&[(0, 0), (0, 6), (2, 3), (4, 6), (4, 0)]],
+        'N' => &[&[(0, 0), (0, 6), (4, 0), (4, 6)]],
+        'O' => &[O],
+        'P' => &[P],
+        'Q' => &[O, &[(2, 2), (4, 0)]],
+        'R' => &[P, &[(2, 3), (4, 0)]],
+        'S' => &[&[
+            (4, 5),
+            (3, 6),
+            (1, 6),
+            (0, 5),
+            (0, 4),
+            (1, 3),
+            (3, 3),
+            (4, 2),
+            (4, 1),
+            (3, 0),
+            (1, 0),
+            (0, 1),
+        ]],
+        'T' => &[&[(0, 6), (4, 6)], &[(2, 6), (2, 0)]],
+        'U' => &[&[(0, 6), (0, 1), (1, 0), (3, 0), (4, 1), (4, 6)]],
+        'V' => &[&[(0, 6), (2, 0), (4, 6)]],
+        'W' => &[&[(0, 6), (1, 0), (2, 3), (3, 0), (4, 6)]],
+        'X' => &[&[(0, 0), (4, 6)], &[(0, 6), (4, 0)]],
+        'Y' => &[&[(0, 6), (2, 3), (4, 6)], &[(2, 3), (2, 0)]],
+        'Z' => &[&[(0, 6), (4, 6), (0, 0), (4, 0)]],
+        '0' => &[O, &[(0, 1), (4, 5)]],
+        '1' => &[&[(1, 5), (2, 6), (2, 0)], &[(1, 0), (3, 0)]],
+        '2' => &[&[(0, 5), (1, 6), (3, 6), (4, 5), (4, 4), (0, 0), (4, 0)]],
+        '3' => &[
+            &[(0, 5), (1, 6), (3, 6), (4, 5), (4, 4), (3, 3)],
+            &[(1, 3), (3, 3), (4, 2), (4, 1), (3, 0), (1, 0), (0, 1)],
+        ],
+        '4' => &[&[(3, 0), (3, 6), (0, 2), (4, 2)]],
+        '5' => &[&[
+            (4, 6),
+            (0, 6),
+            (0, 3),
+            (3, 3),
+            (4, 2),
+            (4, 1),
+            (3, 0),
+            (0, 0),
+        ]],
+        '6' => &[&[
+            (3, 6),
+            (1, 6),
+            (0, 5),
+            (0, 1),
+            (1, 0),
+            (3, 0),
+            (4, 1),
+            (4, 2),
+            (3, 3),
+            (0, 3),
+        ]],
+        '7' => &[&[(0, 6), (4, 6), (1, 0)]],
+        '8' => &[
+            &[
+                (1, 3),
+                (0, 4),
+                (0, 5),
+                (1, 6),
+                (3, 6),
+                (4, 5),
+                (4, 4),
+                (3, 3),
+            ],
+            &[
+                (1, 3),
+                (3, 3),
+                (4, 2),
+                (4, 1),
+                (3, 0),
+                (1, 0),
+                (0, 1),
+                (0, 2),
+                (1, 3),
+            ],
+        ],
+        '9' => &[&[
+            (4, 3),
+            (1, 3),
+            (0, 4),
+            (0, 5),
+            (1, 6),
+            (3, 6),
+            (4, 5),
+            (4, 1),
+            (3, 0),
+            (1, 0),
+        ]],
+        ' ' => &[],
+        '.' => &[&[(2, 0)]],
+        ',' => &[&[(2, 1), (1, -1)]],
+        '!' => &[&[(2, 6), (2, 2)], &[(2, 0)]],
+        '-' => &[&[(1, 3), (3, 3)]],
+        ':' => &[&[(2, 4)], &[(2, 1)]],
+        '\'' => &[&[(2, 6), (2, 4)]],
+        _ => &[
+            &[(0, 5), (1, 6), (3, 6), (4, 5), (4, 4), (2, 3), (2, 2)],
+            &[(2, 0)],
+        ],
+    }
+}
+
+// Width of a line of glyphs, in grid units
+fn line_width(count: usize) -> f32 {
+    count.saturating_sub(1) as f32 * ADVANCE + GLYPH_WIDTH
+}
+
+fn segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = if ab.mag_sq() > 0. {
+        ((p - a).dot(ab) / ab.mag_sq()).clamp(0., 1.)
+    } else {
+        0.
+    };
+    (p - a - ab * t).mag()
+}
+
+fn glyph_distance(p: Vec2, glyph: Glyph) -> f32 {
+    let mut distance = f32::INFINITY;
+    for line in glyph {
+        let point = |&(x, y): &(i8, i8)| Vec2::new(x as f32, y as f32);
+        let start = line.first().map(point).unwrap_or_default();
+        // Single points make dots
+        distance = distance.min((p - start).mag());
+        for pair in line.windows(2) {
+            distance = distance.min(segment_distance(p, point(&pair[0]), point(&pair[1])));
+        }
+    }
+    distance
+}
+
+// Distance to a line of glyphs that are size tall, extruded along z to the
+// depth, and centered on the origin
+pub fn text_distance(p: Vec3, glyphs: &[Glyph], size: f32, depth: f32) -> f32 {
+    let scale = size / GLYPH_HEIGHT;
+    // In grid units, with the first glyph's cell starting at 0
+    let x = p.x / scale + line_width(glyphs.len()) * 0.5;
+    let y = p.y / scale + GLYPH_HEIGHT * 0.5;
+
+    // Only the glyphs next to the point are measured. The others are at
+    // least as far as the edges of those cells, which bounds them.
+    let mut flat = f32::INFINITY;
+    let cell = (x / ADVANCE).floor() as i64;
+    let (first, last) = (cell - 1, cell + 1);
+    for i in first.max(0)..=last.min(glyphs.len() as i64 - 1) {
+        let origin = Vec2::new(i as f32 * ADVANCE, 0.);
+        flat = flat.min(glyph_distance(Vec2::new(x, y) - origin, glyphs[i as usize]));
+    }
+    if first > 0 {
+        flat = flat.min(x - (first as f32 * ADVANCE));
+    }
+    if last < glyphs.len() as i64 - 1 {
+        flat = flat.min((last + 1) as f32 * ADVANCE - x);
+    }
+    let flat = (flat - STROKE) * scale;
+
+    // Extruded, with the flat distance in one direction and z in the other
+    let w = Vec2::new(flat, p.z.abs() - depth * 0.5);
+    w.x.max(w.y).min(0.) + w.max_by_component(Vec2::zero()).mag()
+}
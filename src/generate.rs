@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use ultraviolet::{Rotor3, Vec3};
+
+use crate::distfield::{Sdf, Surface};
+use crate::graph::{Capsule, Sphere, Union};
+use crate::rng::Rng;
+
+// Generators for scenes with many objects, as graph nodes that can be
+// compiled into a bytecode::Program. Randomness comes from the Rng passed
+// in, so the same seed always gives the same scene.
+
+// Joins the nodes into one, or None if there are none. The unions are
+// chained so that a compiled program needs only two stack entries.
+pub fn union_all(nodes: Vec<Box<dyn Sdf>>) -> Option<Box<dyn Sdf>> {
+    nodes.into_iter().reduce(|a, b| Box::new(Union(a, b)))
+}
+
+// Uniform in [min, max)
+fn range(rng: &mut Rng, min: f32, max: f32) -> f32 {
+    min + (max - min) * rng.next_f32()
+}
+
+fn pick<T: Copy>(rng: &mut Rng, items: &[T]) -> T {
+    items[(rng.next_u32() as usize) % items.len()]
+}
+
+// Up to count spheres with radii in the range that don't overlap, placed at
+// random inside the box and given one of the surfaces each. Gives up on a
+// sphere after a number of attempts, so crowded boxes end up with fewer.
+pub fn sphere_pack(
+    rng: &mut Rng,
+    (min, max): (Vec3, Vec3),
+    count: usize,
+    (min_radius, max_radius): (f32, f32),
+    surfaces: &[Surface],
+) -> Vec<Box<dyn Sdf>> {
+    const ATTEMPTS: usize = 32;
+    let mut placed: Vec<(Vec3, f32)> = Vec::new();
+    for _ in 0..count {
+        for _ in 0..ATTEMPTS {
+            let radius = range(rng, min_radius, max_radius);
+            let center = Vec3::new(
+                range(rng, min.x + radius, max.x - radius),
+                range(rng, min.y + radius, max.y - radius),
+                range(rng, min.z + radius, max.z - radius),
+            );
+            if placed
+                .iter()
+                .all(|&(other, r)| (center - other).mag() >= radius + r)
+            {
+                placed.push((center, radius));
+                break;
+            }
+        }
+    }
+    placed
+        .into_iter()
+        .map(|(center, radius)| {
+            Box::new(Sphere {
+                center,
+                radius,
+                surface: pick(rng, surfaces),
+            }) as Box<dyn Sdf>
+        })
+        .collect()
+}
+
+// Calls make with each point of a grid of counts points spaced apart from
+// the origin, each moved at random by up to jitter times the spacing
+pub fn jittered_grid(
+    rng: &mut Rng,
+    origin: Vec3,
+    spacing: Vec3,
+    counts: [u32; 3],
+    jitter: f32,
+    mut make: impl FnMut(&mut Rng, Vec3) -> Box<dyn Sdf>,
+) -> Vec<Box<dyn Sdf>> {
+    let mut nodes = Vec::new();
+    for z in 0..counts[2] {
+        for y in 0..counts[1] {
+            for x in 0..counts[0] {
+                let cell = Vec3::new(x as f32, y as f32, z as f32);
+                let offset = Vec3::new(
+                    range(rng, -jitter, jitter),
+                    range(rng, -jitter, jitter),
+                    range(rng, -jitter, jitter),
+                );
+                nodes.push(make(rng, origin + (cell + offset) * spacing));
+            }
+        }
+    }
+    nodes
+}
+
+// Rewrites every symbol that has a rule with its replacement, the given
+// number of times, starting from the axiom
+pub fn l_system(axiom: &str, rules: &[(char, &str)], iterations: u32) -> String {
+    let rules: HashMap<char, &str> = rules.iter().copied().collect();
+    let mut current = axiom.to_string();
+    for _ in 0..iterations {
+        current = current
+            .chars()
+            .map(|c| {
+                rules
+                    .get(&c)
+                    .map_or_else(|| c.to_string(), |r| r.to_string())
+            })
+            .collect();
+    }
+    current
+}
+
+// How the turtle draws an L-system string as branches
+#[derive(Clone, Copy, Debug)]
+pub struct Turtle {
+    pub length: f32,
+    pub radius: f32,
+    // Turning angle in radians, varied by up to this fraction of itself
+    pub angle: f32,
+    pub jitter: f32,
+    // Scale of the length and radius of each new branch
+    pub taper: f32,
+    pub surface: Surface,
+}
+
+// Draws the commands as capsules from the start, heading up along y:
+//
+//     F    a branch forward
+//     + -  turn left or right, around the local z axis
+//     & ^  pitch down or up, around the local x axis
+//     / \  roll around the heading
+//     [ ]  start a smaller branch, and return from it
+//
+// Other symbols are only used for rewriting, and ignored.
+pub fn turtle(rng: &mut Rng, commands: &str, start: Vec3, turtle: Turtle) -> Vec<Box<dyn Sdf>> {
+    let mut nodes: Vec<Box<dyn Sdf>> = Vec::new();
+    let (mut pos, mut rotation) = (start, Rotor3::identity());
+    let (mut length, mut radius) = (turtle.length, turtle.radius);
+    let mut stack = Vec::new();
+    for c in commands.chars() {
+        let mut angle = || turtle.angle * (1. + range(rng, -turtle.jitter, turtle.jitter));
+        match c {
+            'F' => {
+                let end = pos + rotation * Vec3::unit_y() * length;
+                nodes.push(Box::new(Capsule {
+                    a: pos,
+                    b: end,
+                    radius,
+                    surface: turtle.surface,
+                }));
+                pos = end;
+            }
+            '+' => rotation = rotation * Rotor3::from_rotation_xy(angle()),
+            '-' => rotation = rotation * Rotor3::from_rotation_xy(-angle()),
+            '&' => rotation = rotation * Rotor3::from_rotation_yz(angle()),
+            '^' => rotation = rotation * Rotor3::from_rotation_yz(-angle()),
+            '/' => rotation = rotation * Rotor3::from_rotation_xz(angle()),
+            '\\' => rotation = rotation * Rotor3::from_rotation_xz(-angle()),
+            '[' => {
+                stack.push((pos, rotation, length, radius));
+                length *= turtle.taper;
+                radius *= turtle.taper;
+            }
+            ']' => {
+                if let Some(state) = stack.pop() {
+                    (pos, rotation, length, radius) = state;
+                }
+            }
+            _ => {}
+        }
+        rotation.normalize();
+    }
+    nodes
+}
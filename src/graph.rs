@@ -0,0 +1,284 @@
+use ultraviolet::{Rotor3, Vec3};
+
+use crate::bytecode::Op;
+use crate::distfield::{self, Sample, Sdf, Surface};
+use crate::font::{self, Glyph};
+use crate::validate::{check_ops, check_surface, Warning};
+
+// Scene graph nodes, for scenes that are assembled at runtime rather than
+// written as a single function. Trees of these can be flattened into a
+// bytecode::Program to avoid the virtual calls.
+
+// Checks a node with children by its instructions if it compiles, and
+// otherwise by the instructions of its own and each child in turn, so that
+// trees with fields that don't compile are still checked where they can be
+fn validate_node(node: &dyn Sdf, own: &[Op], children: &[&dyn Sdf], warnings: &mut Vec<Warning>) {
+    let mut ops = Vec::new();
+    if node.compile(&mut ops).is_some() {
+        check_ops(warnings, &ops);
+        return;
+    }
+    check_ops(warnings, own);
+    for child in children {
+        child.validate(warnings);
+    }
+}
+
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+    pub surface: Surface,
+}
+
+impl Sdf for Sphere {
+    fn sample(&self, p: Vec3) -> Sample {
+        distfield::sphere(p, self.center, self.radius, self.surface)
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        ops.push(Op::Sphere {
+            center: self.center,
+            radius: self.radius,
+            surface: self.surface,
+        });
+        Some(())
+    }
+}
+
+pub struct Capsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f32,
+    pub surface: Surface,
+}
+
+impl Sdf for Capsule {
+    fn sample(&self, p: Vec3) -> Sample {
+        distfield::capsule(p, self.a, self.b, self.radius, self.surface)
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        ops.push(Op::Capsule {
+            a: self.a,
+            b: self.b,
+            radius: self.radius,
+            surface: self.surface,
+        });
+        Some(())
+    }
+}
+
+pub struct Mandelbulb {
+    pub center: Vec3,
+    pub scale: f32,
+    pub power: f32,
+    pub surface: Surface,
+}
+
+impl Sdf for Mandelbulb {
+    fn sample(&self, p: Vec3) -> Sample {
+        distfield::mandelbulb(p, self.center, self.scale, self.power, self.surface)
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        ops.push(Op::Mandelbulb {
+            center: self.center,
+            scale: self.scale,
+            power: self.power,
+            surface: self.surface,
+        });
+        Some(())
+    }
+}
+
+// A line of text in the built-in font, with capitals size tall, extruded
+// along z to the depth and centered on the origin. Place it with Transform.
+pub struct Text {
+    glyphs: Vec<Glyph>,
+    pub size: f32,
+    pub depth: f32,
+    pub surface: Surface,
+}
+
+impl Text {
+    pub fn new(text: &str, size: f32, depth: f32, surface: Surface) -> Self {
+        Self {
+            glyphs: text.chars().map(font::glyph).collect(),
+            size,
+            depth,
+            surface,
+        }
+    }
+}
+
+impl Sdf for Text {
+    fn sample(&self, p: Vec3) -> Sample {
+        Sample {
+            distance: font::text_distance(p, &self.glyphs, self.size, self.depth),
+            surface: self.surface,
+        }
+    }
+
+    fn validate(&self, warnings: &mut Vec<Warning>) {
+        for (what, size) in [("text", self.size), ("text depth", self.depth)] {
+            if size <= 0. {
+                warnings.push(Warning::EmptyPrimitive { what, size });
+            }
+        }
+        check_surface(warnings, &self.surface);
+    }
+}
+
+pub struct Union(pub Box<dyn Sdf>, pub Box<dyn Sdf>);
+
+impl Sdf for Union {
+    fn sample(&self, p: Vec3) -> Sample {
+        distfield::union(self.0.sample(p), self.1.sample(p))
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        self.0.compile(ops)?;
+        self.1.compile(ops)?;
+        ops.push(Op::Union);
+        Some(())
+    }
+
+    fn validate(&self, warnings: &mut Vec<Warning>) {
+        validate_node(self, &[], &[&*self.0, &*self.1], warnings);
+    }
+}
+
+pub struct Intersect(pub Box<dyn Sdf>, pub Box<dyn Sdf>);
+
+impl Sdf for Intersect {
+    fn sample(&self, p: Vec3) -> Sample {
+        distfield::intersect(self.0.sample(p), self.1.sample(p))
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        self.0.compile(ops)?;
+        self.1.compile(ops)?;
+        ops.push(Op::Intersect);
+        Some(())
+    }
+
+    fn validate(&self, warnings: &mut Vec<Warning>) {
+        validate_node(self, &[], &[&*self.0, &*self.1], warnings);
+    }
+}
+
+pub struct Invert(pub Box<dyn Sdf>);
+
+impl Sdf for Invert {
+    fn sample(&self, p: Vec3) -> Sample {
+        distfield::invert(self.0.sample(p))
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        self.0.compile(ops)?;
+        ops.push(Op::Invert);
+        Some(())
+    }
+
+    fn validate(&self, warnings: &mut Vec<Warning>) {
+        validate_node(self, &[], &[&*self.0], warnings);
+    }
+}
+
+pub struct Displace {
+    pub scale: f32,
+    pub detail: f32,
+    pub child: Box<dyn Sdf>,
+}
+
+impl Sdf for Displace {
+    fn sample(&self, p: Vec3) -> Sample {
+        distfield::displace(p, self.scale, self.detail, self.child.sample(p))
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        self.child.compile(ops)?;
+        ops.push(Op::Displace {
+            scale: self.scale,
+            detail: self.detail,
+        });
+        Some(())
+    }
+
+    fn validate(&self, warnings: &mut Vec<Warning>) {
+        let own = Op::Displace {
+            scale: self.scale,
+            detail: self.detail,
+        };
+        validate_node(self, &[own], &[&*self.child], warnings);
+    }
+}
+
+pub struct Warp(pub Box<dyn Sdf>);
+
+impl Sdf for Warp {
+    fn sample(&self, p: Vec3) -> Sample {
+        self.0.sample(distfield::warp(p))
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        ops.push(Op::PushWarp);
+        self.0.compile(ops)?;
+        // Warping leaves distances alone, so this only restores the point
+        ops.push(Op::PopTransform { scale: 1. });
+        Some(())
+    }
+
+    fn validate(&self, warnings: &mut Vec<Warning>) {
+        validate_node(self, &[], &[&*self.0], warnings);
+    }
+}
+
+pub struct Transform {
+    pub offset: Vec3,
+    pub rotation: Rotor3,
+    pub scale: f32,
+    pub child: Box<dyn Sdf>,
+}
+
+impl Transform {
+    pub fn translate(offset: Vec3, child: impl Sdf + 'static) -> Self {
+        Self {
+            offset,
+            rotation: Rotor3::identity(),
+            scale: 1.,
+            child: Box::new(child),
+        }
+    }
+}
+
+impl Sdf for Transform {
+    fn sample(&self, p: Vec3) -> Sample {
+        let local = self.rotation.reversed() * (p - self.offset) / self.scale;
+        let s = self.child.sample(local);
+        Sample {
+            distance: s.distance * self.scale,
+            ..s
+        }
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) -> Option<()> {
+        ops.push(Op::PushTransform {
+            offset: self.offset,
+            rotation: self.rotation,
+            scale: self.scale,
+        });
+        self.child.compile(ops)?;
+        ops.push(Op::PopTransform { scale: self.scale });
+        Some(())
+    }
+
+    fn validate(&self, warnings: &mut Vec<Warning>) {
+        let own = Op::PushTransform {
+            offset: self.offset,
+            rotation: self.rotation,
+            scale: self.scale,
+        };
+        validate_node(self, &[own], &[&*self.child], warnings);
+    }
+}
@@ -1,97 +1,945 @@
-mod distfield;
-use distfield::{distfield, Sample, Surface};
+pub mod bytecode;
+pub mod camera;
+pub mod caustics;
+pub mod color;
+pub mod distfield;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod font;
+pub mod generate;
+pub mod graph;
+mod json;
+mod light;
+pub mod map;
+pub mod mipmap;
+pub mod noise;
+pub mod occlusion;
+pub mod post;
+pub mod prepass;
+pub mod query;
+mod render;
+pub mod rng;
+mod scene;
+pub mod scenes;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod sky;
+pub mod toon;
+pub mod validate;
+
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+
+use camera::{Lens, Stereo};
+use distfield::{Bump, Sample, Surface, Wear};
+use light::cone_direction;
+pub use light::{Light, LightGroups};
+pub use render::{pick, render_pixel, render_pixel_hdr, render_pixel_layers, render_rgba};
+use rng::Rng;
+pub use scene::{ClipPlane, Scene, Shader};
+use toon::Toon;
 use ultraviolet::{Lerp, Vec3};
 
+// Rays march with a minimum step of this much per unit of distance they
+// have travelled from the eye, never less than MIN_PRECISION times the
+// scene's unit scale. Distant surfaces are found less precisely, in
+// proportion to their size on screen, and close ones more. Hits may end up
+// this far inside the surface, and normals are estimated over the same
+// distance.
+const PRECISION_PER_DISTANCE: f32 = 1e-4;
+const MIN_PRECISION: f32 = 1e-3;
+// Reflection chains are cut off by Russian roulette below this throughput
+const ROULETTE_THRESHOLD: f32 = 0.1;
+// With roulette enabled, this only guards against endless mirror chains
+const ROULETTE_MAX_BOUNCES: usize = 64;
+const BUMP_OCTAVES: u32 = 4;
+// Thickness is measured up to this many scatter depths, beyond which hardly
+// any light gets through
+const SCATTER_MAX_DEPTHS: f32 = 8.;
+// Visible range of wavelengths in nanometers, for Settings::dispersion
+const MIN_WAVELENGTH: f32 = 380.;
+const MAX_WAVELENGTH: f32 = 720.;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub max_bounces: usize,
+    pub samples: u32,
+    pub seed: u64,
+    pub roulette: bool,
+    pub lens: Lens,
+    pub stereo: Option<Stereo>,
+    // Starts reflected rays this many times the precision of the hit away
+    // from the surface along its normal, instead of marching them out of
+    // the object. Saves samples per bounce, but may start inside of thin or
+    // concave features.
+    pub reflection_offset: Option<f32>,
+    pub toon: Option<Toon>,
+    // Traces each sample for this many wavelengths spread over the visible
+    // spectrum, combined into RGB, so that refractive surfaces with
+    // dispersion split light into its colors
+    pub dispersion: Option<u32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_bounces: 5,
+            samples: 1,
+            seed: 0,
+            roulette: false,
+            lens: Lens::Perspective,
+            stereo: None,
+            reflection_offset: None,
+            toon: None,
+            dispersion: None,
+        }
+    }
+}
+
+// A point on a surface being shaded
 #[derive(Clone, Copy, Debug)]
-pub struct Light {
-    pos: Vec3,
-    color: Vec3,
+pub struct Hit {
+    pub p: Vec3,
+    pub n: Vec3,
+    pub surface: Surface,
+    // Length of the path from the eye, see precision
+    pub traveled: f32,
+}
+
+fn precision(scene: &Scene, traveled: f32) -> f32 {
+    (traveled * PRECISION_PER_DISTANCE).max(MIN_PRECISION * scene.unit_scale)
 }
 
-impl Light {
-    pub fn new(pos: Vec3, color: Vec3) -> Self {
-        Self { pos, color }
+// Receives the contribution of each light to a traced color, weighted by
+// how much the current ray contributes to the pixel, per channel for single
+// wavelengths. The entry after the lights collects everything else, such as
+// the background in reflections.
+struct Layers<'a> {
+    layers: Option<&'a mut [Vec3]>,
+    weight: Vec3,
+}
+
+impl Layers<'_> {
+    fn scaled(&mut self, factor: f32) -> Layers<'_> {
+        self.tinted(Vec3::broadcast(factor))
     }
 
-    fn in_shadow(&self, point: Vec3) -> bool {
-        let l = (self.pos - point).normalized();
-        // Step out of object
-        let p = raycast_out(point, l);
-        // Check for any objects while tracing towards the light source
-        raycast(p, l, |p| (self.pos - p).dot(l) > 0.).is_some()
+    fn tinted(&mut self, factor: Vec3) -> Layers<'_> {
+        Layers {
+            layers: self.layers.as_deref_mut(),
+            weight: self.weight * factor,
+        }
     }
 
-    fn diffuse(&self, p: Vec3, n: Vec3) -> f32 {
-        let l = (self.pos - p).normalized();
-        n.dot(l).min(1.0).max(0.0)
+    fn add(&mut self, index: usize, rgb: Vec3) {
+        if let Some(layers) = self.layers.as_deref_mut() {
+            layers[index] += rgb * self.weight;
+        }
+    }
+
+    fn add_other(&mut self, rgb: Vec3) {
+        if let Some(layers) = self.layers.as_deref_mut() {
+            let last = layers.len() - 1;
+            layers[last] += rgb * self.weight;
+        }
+    }
+}
+
+// Fraction of the light reaching the hit that is blocked by other objects
+fn shadow_amount(scene: &Scene, rng: &mut Rng, hit: &Hit) -> f32 {
+    let (mut lit, mut unshadowed) = (0., 0.);
+    for light in scene
+        .lights
+        .iter()
+        .filter(|light| light.affects(&hit.surface))
+    {
+        let target = light.sample_point(scene, hit.p, rng);
+        let irradiance = light.color.component_max() * light.diffuse(hit.p, target, hit.n);
+        unshadowed += irradiance;
+        if !light.in_shadow(scene, hit, target) {
+            lit += irradiance;
+        }
+    }
+    if unshadowed > 0. {
+        1. - lit / unshadowed
+    } else {
+        0.
     }
 }
 
-fn apply_lights<'a>(p: Vec3, s: Surface, n: Vec3, lights: impl Iterator<Item = &'a Light>) -> Vec3 {
+fn apply_lights(scene: &Scene, rng: &mut Rng, layers: &mut Layers, hit: &Hit) -> Vec3 {
+    let (p, n, s) = (hit.p, hit.n, hit.surface);
     let mut rgb = Vec3::new(0., 0., 0.);
-    for light in lights {
-        if !light.in_shadow(p) {
-            rgb += light.color * s.color * light.diffuse(p, n);
+    for (i, light) in scene.lights.iter().enumerate() {
+        if !light.affects(&s) {
+            continue;
         }
+        let target = light.sample_point(scene, p, rng);
+        let mut contribution = Vec3::zero();
+        let mut amount = light.diffuse(p, target, n);
+        if let Some(bands) = s.toon {
+            amount = toon::quantize(amount, bands);
+        }
+        let diffuse = light.color * s.color * amount;
+        if !light.in_shadow(scene, hit, target) {
+            contribution += diffuse;
+        } else if let Some(tint) = light.shadow_tint.or(scene.shadow_tint) {
+            contribution += diffuse * tint;
+        }
+        if let Some(scatter) = s.scatter {
+            // Light passing through a translucent object falls off with its
+            // thickness towards the light, wrapped around to the far side
+            let l = (target - p).normalized();
+            let t = thickness(scene, p, l, scatter.depth * SCATTER_MAX_DEPTHS);
+            let transmission = scatter.color * (-t / scatter.depth).exp();
+            contribution += light.color * transmission * (0.5 - 0.5 * n.dot(l));
+        }
+        layers.add(i, contribution);
+        rgb += contribution;
+    }
+    if let Some(caustics) = &scene.caustics {
+        let focused = caustics.irradiance(p, n) * s.color;
+        layers.add_other(focused);
+        rgb += focused;
     }
     rgb
 }
 
-fn raycast<F>(from: Vec3, dir: Vec3, condition: F) -> Option<(Sample, Vec3)>
+// Direct lighting at the hit, or the color from the object's shader, with
+// the surface's own emission
+fn shade(scene: &Scene, rng: &mut Rng, layers: &mut Layers, hit: &Hit) -> Vec3 {
+    let emission = hit.surface.emission;
+    layers.add_other(emission);
+    match scene.shader(&hit.surface) {
+        Some(shader) => {
+            let rgb = shader(hit);
+            layers.add_other(rgb);
+            rgb + emission
+        }
+        None => apply_lights(scene, rng, layers, hit) + emission,
+    }
+}
+
+// Phong exponent for a roughness in (0, 1]
+fn glossy_exponent(roughness: f32) -> f32 {
+    (2. / (roughness * roughness) - 2.).max(0.)
+}
+
+fn glossy_pdf(r: Vec3, dir: Vec3, exponent: f32) -> f32 {
+    (exponent + 1.) / (2. * PI) * r.dot(dir).max(0.).powf(exponent)
+}
+
+// Highlights from lights on a glossy surface with mirror direction r, sampled
+// from both the lights and the glossy lobe (dir) and combined with multiple
+// importance sampling, using the power heuristic
+fn glossy_highlights(
+    scene: &Scene,
+    rng: &mut Rng,
+    layers: &mut Layers,
+    hit: &Hit,
+    r: Vec3,
+    dir: Vec3,
+) -> Vec3 {
+    let (p, n) = (hit.p, hit.n);
+    let exponent = glossy_exponent(hit.surface.roughness);
+    let mut rgb = Vec3::zero();
+    for (i, light) in scene.lights.iter().enumerate() {
+        if !light.affects(&hit.surface) {
+            continue;
+        }
+        let mut contribution = Vec3::zero();
+        let target = light.sample_point(scene, p, rng);
+        let l = (target - p).normalized();
+        if l.dot(n) > 0. && !light.in_shadow(scene, hit, target) {
+            let pdf_light = light.pdf(p);
+            let pdf_glossy = glossy_pdf(r, l, exponent);
+            let weight = if pdf_light.is_infinite() {
+                1.
+            } else {
+                pdf_light * pdf_light / (pdf_light * pdf_light + pdf_glossy * pdf_glossy)
+            };
+            contribution += light.color * pdf_glossy * weight;
+        }
+
+        if let Some(t) = light.intersect(p, dir) {
+            if !light.in_shadow(scene, hit, p + dir * t) {
+                let pdf_light = light.pdf(p);
+                let pdf_glossy = glossy_pdf(r, dir, exponent);
+                let weight =
+                    pdf_glossy * pdf_glossy / (pdf_light * pdf_light + pdf_glossy * pdf_glossy);
+                // Radiance of an area light is its irradiance over the solid angle
+                contribution += light.color * pdf_light * weight;
+            }
+        }
+        layers.add(i, contribution);
+        rgb += contribution;
+    }
+    rgb
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RayKind {
+    Camera,
+    Shadow,
+    Reflection,
+}
+
+impl RayKind {
+    fn sees(self, surface: &Surface) -> bool {
+        match self {
+            RayKind::Camera => surface.visibility.camera,
+            RayKind::Shadow => surface.visibility.shadow,
+            RayKind::Reflection => surface.visibility.reflection,
+        }
+    }
+}
+
+pub(crate) fn raycast<F>(
+    scene: &Scene,
+    from: Vec3,
+    dir: Vec3,
+    kind: RayKind,
+    traveled: f32,
+    condition: F,
+) -> Option<(Sample, Vec3)>
 where
     F: Fn(Vec3) -> bool,
 {
     let mut p = from;
+    let mut traveled = traveled;
+    let mipmap = scene.empty_space.as_ref();
+    // Whether the ray is away from surfaces, where it may be in empty space
+    let mut away = true;
     while condition(p) {
-        let s = distfield(p);
-        if s.distance <= 0. {
-            return Some((s, p));
+        // Jump through empty space without sampling, where that's further
+        // than the smallest step
+        let skip = mipmap
+            .filter(|_| away)
+            .and_then(|mipmap| mipmap.skip(p, dir));
+        if let Some(step) = skip.filter(|&step| step > precision(scene, traveled)) {
+            p += dir * step;
+            traveled += step;
+            continue;
         }
-        let step = if s.distance > 0.01 { s.distance } else { 0.01 };
+        let s = scene.sample(p);
+        // Hidden surfaces are marched through, towards where they end
+        let distance = if s.distance <= 0. {
+            if kind.sees(&s.surface) {
+                return Some((s, p));
+            }
+            -s.distance
+        } else {
+            s.distance
+        };
+        away = mipmap.is_some_and(|mipmap| s.distance > mipmap.diagonal());
+        let step = distance.max(precision(scene, traveled));
         p += dir * step;
+        traveled += step;
     }
     None
 }
 
-fn raycast_out(from: Vec3, dir: Vec3) -> Vec3 {
+// First point along the ray outside of the object it starts in, or None if
+// it doesn't get out within the scene's bounds
+pub(crate) fn raycast_out(scene: &Scene, from: Vec3, dir: Vec3, traveled: f32) -> Option<Vec3> {
+    let extent = scene.ray_extent(from, dir);
     let mut p = from;
     loop {
-        let f = -1.0 * distfield(p).distance;
+        let f = -scene.sample(p).distance;
         if f < 0. {
+            return Some(p);
+        }
+        if (p - from).mag_sq() > extent * extent {
+            return None;
+        }
+        p += dir * f.max(precision(scene, traveled));
+    }
+}
+
+// Distance from p along dir until leaving the scene's objects, up to max
+fn thickness(scene: &Scene, p: Vec3, dir: Vec3, max: f32) -> f32 {
+    let mut t = 0.;
+    while t < max {
+        let d = scene.sample(p + dir * t).distance;
+        if d > 0. {
             break;
         }
-        let step = if f > 0.01 { f } else { 0.01 };
-        p += dir * step;
+        t += (-d).max(0.01 * scene.unit_scale);
     }
-    p
+    t.min(max)
 }
 
-fn guess_normal(p: Vec3) -> Vec3 {
-    let delta = 0.01;
+// Where the field has no slope, such as in the middle of a sphere, any
+// direction will do, so this returns up rather than NaN
+fn guess_normal(scene: &Scene, p: Vec3, delta: f32) -> Vec3 {
     let dx = Vec3::new(delta, 0., 0.);
     let dy = Vec3::new(0., delta, 0.);
     let dz = Vec3::new(0., 0., delta);
-    Vec3::new(
-        (distfield(p + dx).distance - distfield(p - dx).distance) / (delta * 2.0),
-        (distfield(p + dy).distance - distfield(p - dy).distance) / (delta * 2.0),
-        (distfield(p + dz).distance - distfield(p - dz).distance) / (delta * 2.0),
-    )
-    .normalized()
-}
-
-pub fn raytrace(from: Vec3, dir: Vec3, lights: &[Light], max_bounces: usize) -> Option<Vec3> {
-    raycast(from, dir, |p| (from - p).mag_sq() < 1000000.).map(|(s, p)| {
-        let n = guess_normal(p);
-        let mut rgb = apply_lights(p, s.surface, n, lights.iter());
-
-        let reflectivity = s.surface.reflectivity;
-        if reflectivity > 0.0 && max_bounces > 0 {
-            let r = dir.reflected(n);
-            let p = raycast_out(p, r);
-            let reflected_color =
-                raytrace(p, r, lights, max_bounces - 1).unwrap_or_else(|| Vec3::new(0.3, 0.3, 0.3));
-            rgb = rgb.lerp(reflected_color, reflectivity);
-        }
-        rgb
+    let gradient = Vec3::new(
+        (scene.sample(p + dx).distance - scene.sample(p - dx).distance) / (delta * 2.0),
+        (scene.sample(p + dy).distance - scene.sample(p - dy).distance) / (delta * 2.0),
+        (scene.sample(p + dz).distance - scene.sample(p - dz).distance) / (delta * 2.0),
+    );
+    if gradient.mag_sq() > 0. {
+        gradient.normalized()
+    } else {
+        Vec3::unit_y()
+    }
+}
+
+// Point and normal where a camera ray first meets a surface, for finding
+// outlines
+pub(crate) fn camera_hit(scene: &Scene, from: Vec3, dir: Vec3) -> Option<(Vec3, Vec3)> {
+    let extent = scene.ray_extent(from, dir);
+    let (_, p) = raycast(scene, from, dir, RayKind::Camera, 0., |p| {
+        (from - p).mag_sq() < extent * extent
+    })?;
+    let traveled = (p - from).mag();
+    Some((p, guess_normal(scene, p, precision(scene, traveled))))
+}
+
+// Tilts the normal against the slope of the bump noise along the surface, as
+// if it were displaced by noise with a height of scale * strength
+fn bump_normal(p: Vec3, n: Vec3, bump: Bump) -> Vec3 {
+    let gradient = noise::fbm_gradient(p / bump.scale, BUMP_OCTAVES);
+    let along_surface = gradient - n * gradient.dot(n);
+    (n - along_surface * bump.strength).normalized()
+}
+
+// The color with edge wear and cavity dirt, blended in fully where the
+// surface curves with about the wear radius
+fn worn_color(scene: &Scene, p: Vec3, color: Vec3, wear: Wear) -> Vec3 {
+    let amount = scene.curvature(p, wear.radius) * wear.radius;
+    color
+        .lerp(wear.edge, amount.clamp(0., 1.))
+        .lerp(wear.cavity, (-amount).clamp(0., 1.))
+}
+
+// Color and alpha seen along the ray, where alpha is only below 1 for shadow
+// catchers, see Surface::shadow_catcher. Nothing may be within the start
+// distance along it, which is skipped, or 0 to march from the eye.
+pub fn raytrace(
+    scene: &Scene,
+    settings: &Settings,
+    rng: &mut Rng,
+    from: Vec3,
+    dir: Vec3,
+    start: f32,
+) -> Option<(Vec3, f32)> {
+    let mut layers = Layers {
+        layers: None,
+        weight: Vec3::one(),
+    };
+    trace_camera(scene, settings, rng, &mut layers, from, dir, start)
+}
+
+// Like raytrace, but also adds the contribution of each light to the first
+// scene.lights.len() entries of layers, and everything else to the last one
+pub fn raytrace_layers(
+    scene: &Scene,
+    settings: &Settings,
+    rng: &mut Rng,
+    from: Vec3,
+    dir: Vec3,
+    start: f32,
+    layers: &mut [Vec3],
+) -> Option<(Vec3, f32)> {
+    assert_eq!(layers.len(), scene.lights.len() + 1);
+    let mut layers = Layers {
+        layers: Some(layers),
+        weight: Vec3::one(),
+    };
+    trace_camera(scene, settings, rng, &mut layers, from, dir, start)
+}
+
+// Response in RGB to light of the wavelength, without the parts outside of
+// the gamut, which are negative and don't cancel out over a few samples
+fn spectral_response(nm: f32) -> Vec3 {
+    color::wavelength(nm).max_by_component(Vec3::zero())
+}
+
+// Mean of spectral_response over the visible spectrum
+fn mean_spectral_response() -> Vec3 {
+    static MEAN: OnceLock<Vec3> = OnceLock::new();
+    *MEAN.get_or_init(|| {
+        let steps = (MAX_WAVELENGTH - MIN_WAVELENGTH) as u32;
+        let sum = (0..steps).fold(Vec3::zero(), |sum, i| {
+            let t = (i as f32 + 0.5) / steps as f32;
+            sum + spectral_response(MIN_WAVELENGTH + (MAX_WAVELENGTH - MIN_WAVELENGTH) * t)
+        });
+        sum / steps as f32
     })
 }
+
+// Traces a camera ray, once for each wavelength with Settings::dispersion.
+// The wavelengths are spread evenly over the visible spectrum with some
+// jitter, and each one's color is weighted by its response in RGB over the
+// mean response, so that white light stays white on average however few
+// wavelengths there are.
+fn trace_camera(
+    scene: &Scene,
+    settings: &Settings,
+    rng: &mut Rng,
+    layers: &mut Layers,
+    eye: Vec3,
+    dir: Vec3,
+    start: f32,
+) -> Option<(Vec3, f32)> {
+    let from = eye + dir * start;
+    let count = match settings.dispersion {
+        Some(count) => count.max(1),
+        None => return trace(scene, settings, rng, layers, from, dir, start, 0, 1.0, None),
+    };
+    let (mut rgb, mut alpha) = (Vec3::zero(), 0.);
+    let mean = mean_spectral_response();
+    for i in 0..count {
+        let t = (i as f32 + rng.next_f32()) / count as f32;
+        let nm = MIN_WAVELENGTH + (MAX_WAVELENGTH - MIN_WAVELENGTH) * t;
+        let response = spectral_response(nm) / mean;
+        let mut layers = layers.tinted(response / count as f32);
+        if let Some((color, a)) = trace(
+            scene,
+            settings,
+            rng,
+            &mut layers,
+            from,
+            dir,
+            start,
+            0,
+            1.0,
+            Some(nm),
+        ) {
+            rgb += color * response * a;
+            alpha += a;
+        }
+    }
+    if alpha == 0. {
+        return None;
+    }
+    Some((rgb / alpha, alpha / count as f32))
+}
+
+// Last point inside and first point outside where a ray starting inside an
+// object leaves it, close to either side of its surface, or None if it
+// doesn't get out within the scene's bounds
+fn leave_object(scene: &Scene, from: Vec3, dir: Vec3) -> Option<(Vec3, Vec3)> {
+    leave(scene, from, dir, |s| s.distance <= 0.)
+}
+
+// Like leave_object, for the part of the field where the samples are within
+fn leave(
+    scene: &Scene,
+    from: Vec3,
+    dir: Vec3,
+    within: impl Fn(&Sample) -> bool,
+) -> Option<(Vec3, Vec3)> {
+    // March to where the ray leaves, keeping the last point inside
+    let extent = scene.ray_extent(from, dir);
+    let mut inside = from;
+    let mut p = from;
+    loop {
+        let s = scene.sample(p);
+        if !within(&s) {
+            break;
+        }
+        if (p - from).mag_sq() > extent * extent {
+            return None;
+        }
+        inside = p;
+        p += dir * (-s.distance).max(0.01 * scene.unit_scale);
+    }
+    // Narrow down the crossing, so the surface is shaded from just inside it,
+    // where lights inside the object reach it
+    for _ in 0..8 {
+        let mid = (inside + p) * 0.5;
+        if within(&scene.sample(mid)) {
+            inside = mid;
+        } else {
+            p = mid;
+        }
+    }
+    Some((inside, p))
+}
+
+// Where a ray inside a refractive object leaves its medium
+pub(crate) struct Crossing {
+    // Last point in the medium, and the first one past it
+    pub inside: Vec3,
+    pub beyond: Vec3,
+    // Facing out of the medium
+    pub n: Vec3,
+    // The object on the other side, for objects touching or carved into
+    // each other, or None outside
+    pub next: Option<Surface>,
+}
+
+// Whether light passes between the surfaces' objects without bending or
+// changing how it's absorbed
+fn same_medium(a: &Surface, b: &Surface) -> bool {
+    a.ior == b.ior && a.dispersion == b.dispersion && a.absorption == b.absorption
+}
+
+// Follows a ray from inside a refractive object to where it leaves the
+// object's medium, into another object or outside. Objects nested in each
+// other are told apart by their surfaces, so a bubble carved out of water
+// inside a glass is seen as such whichever way a ray comes in, and only
+// the media on either side of each crossing matter.
+pub(crate) fn leave_medium(
+    scene: &Scene,
+    from: Vec3,
+    dir: Vec3,
+    traveled: f32,
+) -> Option<Crossing> {
+    let medium = scene.sample(from).surface;
+    let (inside, beyond) = leave(scene, from, dir, |s| {
+        s.distance <= 0. && same_medium(&s.surface, &medium)
+    })?;
+    let next = Some(scene.sample(beyond))
+        .filter(|s| s.distance <= 0.)
+        .map(|s| s.surface);
+    let delta = precision(scene, traveled + (inside - from).mag());
+    let n = if next.is_some() {
+        // The field is creased where objects touch, so the normal is found
+        // back inside the medium, first along the ray and then against the
+        // normal found there
+        let n = guess_normal(scene, inside - dir * 2. * delta, delta);
+        guess_normal(scene, inside - n * 2. * delta, delta)
+    } else {
+        guess_normal(scene, inside, delta)
+    };
+    Some(Crossing {
+        inside,
+        beyond,
+        n,
+        next,
+    })
+}
+
+// Direction a ray bends to where it crosses from a medium with one index of
+// refraction into another, through a surface with normal n facing the
+// second, and the fraction of light reflected instead. All of it is
+// reflected past the critical angle, leaving no direction.
+pub(crate) fn bend(dir: Vec3, n: Vec3, ior_a: f32, ior_b: f32) -> (Vec3, f32) {
+    let out = dir.refracted(-n, ior_a / ior_b);
+    if out == Vec3::zero() {
+        return (out, 1.);
+    }
+    let cos_theta = if ior_a > ior_b {
+        out.dot(n)
+    } else {
+        dir.dot(n)
+    };
+    (out, fresnel(cos_theta, ior_a, ior_b))
+}
+
+// Fraction of light reflected where it crosses between indices of refraction,
+// by Schlick's approximation, for cos_theta on the side with the lower index
+fn fresnel(cos_theta: f32, ior_a: f32, ior_b: f32) -> f32 {
+    let r0 = ((ior_a - ior_b) / (ior_a + ior_b)).powi(2);
+    r0 + (1. - r0) * (1. - cos_theta.clamp(0., 1.)).powi(5)
+}
+
+// Seen by reflections and refractions that don't find anything, without a sky
+fn background(layers: &mut Layers) -> Vec3 {
+    let background = Vec3::new(0.3, 0.3, 0.3);
+    layers.add_other(background);
+    background
+}
+
+// Light seen through a refractive object along dir, which has bent into it
+// at from. It's absorbed on the way through, and bends again where it
+// leaves into another medium, or reflects back inside past the critical
+// angle. Opaque objects inside are shaded where the ray meets them.
+#[allow(clippy::too_many_arguments)]
+fn trace_inside(
+    scene: &Scene,
+    settings: &Settings,
+    rng: &mut Rng,
+    layers: &mut Layers,
+    from: Vec3,
+    dir: Vec3,
+    traveled: f32,
+    depth: usize,
+    throughput: f32,
+    wavelength: Option<f32>,
+) -> Vec3 {
+    let surface = scene.sample(from).surface;
+    let crossing = match leave_medium(scene, from, dir, traveled) {
+        Some(crossing) => crossing,
+        None => return Vec3::zero(),
+    };
+    let Crossing { inside, n, .. } = crossing;
+    let distance = (inside - from).mag();
+    let traveled = traveled + distance;
+    let transmittance = match surface.absorption {
+        Some(absorption) => absorption.transmittance(distance),
+        None => Vec3::one(),
+    };
+    // Layers only take a brightness, so the tint is left out of them
+    let brightness = (transmittance.x + transmittance.y + transmittance.z) / 3.;
+    let mut layers = layers.scaled(brightness);
+    let throughput = throughput * brightness;
+
+    let next_ior = match crossing.next {
+        None => 1.,
+        Some(next) => match next.refractive_index(wavelength) {
+            Some(ior) => ior,
+            None => {
+                let hit = Hit {
+                    p: crossing.beyond,
+                    n: -n,
+                    surface: next,
+                    traveled,
+                };
+                return shade(scene, rng, &mut layers, &hit) * transmittance;
+            }
+        },
+    };
+    let ior = surface.refractive_index(wavelength).unwrap_or(1.);
+    let (out, reflected) = bend(dir, n, ior, next_ior);
+    let mut rgb = Vec3::zero();
+    if reflected < 1. {
+        let weight = 1. - reflected;
+        let mut layers = layers.scaled(weight);
+        let throughput = throughput * weight;
+        let color = match crossing.next {
+            Some(_) => trace_inside(
+                scene,
+                settings,
+                rng,
+                &mut layers,
+                crossing.beyond,
+                out,
+                traveled,
+                depth + 1,
+                throughput,
+                wavelength,
+            ),
+            None => trace(
+                scene,
+                settings,
+                rng,
+                &mut layers,
+                crossing.beyond,
+                out,
+                traveled,
+                depth + 1,
+                throughput,
+                wavelength,
+            )
+            .map(|(color, _)| color)
+            .unwrap_or_else(|| background(&mut layers)),
+        };
+        rgb += color * weight;
+    }
+    if reflected > 0. && depth < settings.max_bounces {
+        let mut layers = layers.scaled(reflected);
+        let color = trace_inside(
+            scene,
+            settings,
+            rng,
+            &mut layers,
+            inside,
+            dir.reflected(-n),
+            traveled,
+            depth + 1,
+            throughput * reflected,
+            wavelength,
+        );
+        rgb += color * reflected;
+    }
+    rgb * transmittance
+}
+
+// Traces a camera ray starting inside an object, which sees the inner side of
+// its surface. Two-sided surfaces are shaded facing the eye, while others are
+// looked through as if the camera was outside.
+#[allow(clippy::too_many_arguments)]
+fn trace_from_inside(
+    scene: &Scene,
+    settings: &Settings,
+    rng: &mut Rng,
+    layers: &mut Layers,
+    from: Vec3,
+    dir: Vec3,
+    throughput: f32,
+    wavelength: Option<f32>,
+) -> Option<(Vec3, f32)> {
+    let (inside, p) = leave_object(scene, from, dir)?;
+    let mut surface = scene.sample(inside).surface;
+    surface.toon = surface.toon.or(settings.toon.map(|toon| toon.bands));
+    if !surface.two_sided {
+        let traveled = (p - from).mag();
+        return trace(
+            scene, settings, rng, layers, p, dir, traveled, 0, throughput, wavelength,
+        );
+    }
+    // Facing the eye
+    if let Some(wear) = surface.wear {
+        surface.color = worn_color(scene, inside, surface.color, wear);
+    }
+    let traveled = (inside - from).mag();
+    let hit = Hit {
+        p: inside,
+        n: -guess_normal(scene, inside, precision(scene, traveled)),
+        surface,
+        traveled,
+    };
+    // Reflections aren't followed, as they would lead back into the object
+    Some((shade(scene, rng, layers, &hit), 1.))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn trace(
+    scene: &Scene,
+    settings: &Settings,
+    rng: &mut Rng,
+    layers: &mut Layers,
+    from: Vec3,
+    dir: Vec3,
+    traveled: f32,
+    depth: usize,
+    throughput: f32,
+    wavelength: Option<f32>,
+) -> Option<(Vec3, f32)> {
+    if depth == 0 && scene.sample(from).distance <= 0. {
+        return trace_from_inside(
+            scene, settings, rng, layers, from, dir, throughput, wavelength,
+        );
+    }
+    let kind = if depth == 0 {
+        RayKind::Camera
+    } else {
+        RayKind::Reflection
+    };
+    let extent = scene.ray_extent(from, dir);
+    let (s, p) = match raycast(scene, from, dir, kind, traveled, |p| {
+        (from - p).mag_sq() < extent * extent
+    }) {
+        Some(hit) => hit,
+        None => {
+            return scene.sky.map(|sky| {
+                let color = sky.color(dir);
+                layers.add_other(color);
+                (color, 1.)
+            })
+        }
+    };
+    let traveled = traveled + (p - from).mag();
+    let geometric_n = guess_normal(scene, p, precision(scene, traveled));
+    let n = match s.surface.bump {
+        Some(bump) => bump_normal(p, geometric_n, bump),
+        None => geometric_n,
+    };
+    let mut surface = s.surface;
+    if let Some(wear) = surface.wear {
+        surface.color = worn_color(scene, p, surface.color, wear);
+    }
+    surface.toon = surface.toon.or(settings.toon.map(|toon| toon.bands));
+    let hit = Hit {
+        p,
+        n,
+        surface,
+        traveled,
+    };
+    if s.surface.shadow_catcher {
+        return Some((Vec3::zero(), shadow_amount(scene, rng, &hit)));
+    }
+
+    let max_bounces = if settings.roulette {
+        ROULETTE_MAX_BOUNCES
+    } else {
+        settings.max_bounces
+    };
+    // Refractive surfaces let through what they don't reflect, instead of
+    // being lit
+    let refraction = match s.surface.refractive_index(wavelength) {
+        Some(ior) if depth < max_bounces => Some(bend(dir, -n, 1., ior)),
+        _ => None,
+    };
+    let reflectivity = match refraction {
+        Some((_, reflected)) => s.surface.reflectivity + (1. - s.surface.reflectivity) * reflected,
+        None => s.surface.reflectivity,
+    };
+    let reflects = reflectivity > 0.0 && depth < max_bounces;
+    let local_weight = if reflects { 1.0 - reflectivity } else { 1.0 };
+    let mut rgb = match refraction {
+        Some((into, _)) if into != Vec3::zero() => {
+            let emission = s.surface.emission;
+            let mut layers = layers.scaled(local_weight);
+            layers.add_other(emission);
+            let throughput = throughput * local_weight;
+            emission
+                + trace_inside(
+                    scene,
+                    settings,
+                    rng,
+                    &mut layers,
+                    p,
+                    into,
+                    traveled,
+                    depth + 1,
+                    throughput,
+                    wavelength,
+                )
+        }
+        _ => shade(scene, rng, &mut layers.scaled(local_weight), &hit),
+    };
+
+    if reflects {
+        // Terminate dim chains at random, scaling up the survivors to compensate
+        let throughput = throughput * reflectivity;
+        let survival = if settings.roulette {
+            (throughput / ROULETTE_THRESHOLD).min(1.0)
+        } else {
+            1.0
+        };
+        if survival >= 1.0 || rng.next_f32() < survival {
+            let mut layers = layers.scaled(reflectivity / survival);
+            let mut mirror = dir.reflected(n);
+            if s.surface.bump.is_some() && mirror.dot(geometric_n) < 0. {
+                // Keep reflections off bumps tilted away from the eye outside
+                mirror = mirror.reflected(geometric_n);
+            }
+            let mut r = mirror;
+            let mut highlights = Vec3::zero();
+            if s.surface.roughness > 0. {
+                // Glossy reflection, sampled from a Phong lobe around the mirror direction
+                let exponent = glossy_exponent(s.surface.roughness);
+                let cos_theta = rng.next_f32().powf(1. / (exponent + 1.));
+                r = cone_direction(mirror, cos_theta, 2. * PI * rng.next_f32());
+                highlights = glossy_highlights(scene, rng, &mut layers, &hit, mirror, r);
+            }
+            let reflected_color = if r.dot(n) > 0. || s.surface.roughness == 0. {
+                let origin = match settings.reflection_offset {
+                    Some(offset) => Some(p + geometric_n * (offset * precision(scene, traveled))),
+                    None => raycast_out(scene, p, r, traveled),
+                };
+                origin
+                    .and_then(|p| {
+                        trace(
+                            scene,
+                            settings,
+                            rng,
+                            &mut layers,
+                            p,
+                            r,
+                            traveled,
+                            depth + 1,
+                            throughput,
+                            wavelength,
+                        )
+                    })
+                    .map(|(color, _)| color)
+                    .unwrap_or_else(|| background(&mut layers))
+            } else {
+                // Sampled below the surface
+                Vec3::zero()
+            };
+            rgb = rgb.lerp((reflected_color + highlights) / survival, reflectivity);
+        } else {
+            rgb *= 1.0 - reflectivity;
+        }
+    }
+    Some((rgb, 1.))
+}
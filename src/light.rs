@@ -0,0 +1,318 @@
+use std::f32::consts::PI;
+
+use ultraviolet::Vec3;
+
+use crate::color;
+use crate::distfield::Surface;
+use crate::json::Json;
+use crate::rng::Rng;
+use crate::validate::{check_color, Warning};
+use crate::{raycast, raycast_out, Hit, RayKind, Scene};
+
+// Names for the bits used in light and surface masks
+#[derive(Clone, Default, Debug)]
+pub struct LightGroups {
+    names: Vec<String>,
+}
+
+impl LightGroups {
+    // Mask for the named group, allocating it if needed. Returns None once
+    // all 32 groups are taken.
+    pub fn mask(&mut self, name: &str) -> Option<u32> {
+        let index = match self.names.iter().position(|group| group == name) {
+            Some(index) => index,
+            None if self.names.len() < 32 => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+            None => return None,
+        };
+        Some(1 << index)
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub(crate) fn to_json(&self) -> Json {
+        Json::Array(self.names.iter().map(|name| name.as_str().into()).collect())
+    }
+
+    pub(crate) fn from_json(json: &Json) -> Result<Self, String> {
+        let names: Option<Vec<String>> = json
+            .array()
+            .ok_or("invalid light groups")?
+            .iter()
+            .map(|name| Some(name.str()?.to_string()))
+            .collect();
+        match names {
+            Some(names) if names.len() <= 32 => Ok(Self { names }),
+            _ => Err("invalid light groups".into()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    // For directional lights, this is the direction towards the light
+    pos: Vec3,
+    pub(crate) color: Vec3,
+    radius: f32,
+    directional: bool,
+    groups: u32,
+    // Fraction of the light that still reaches shadowed surfaces
+    pub(crate) shadow_tint: Option<Vec3>,
+}
+
+impl Light {
+    pub fn new(pos: Vec3, color: Vec3) -> Self {
+        Self {
+            pos,
+            color,
+            radius: 0.,
+            directional: false,
+            groups: u32::MAX,
+            shadow_tint: None,
+        }
+    }
+
+    // A light with the color of a black body at the given temperature in
+    // kelvin, see color::kelvin
+    pub fn from_kelvin(pos: Vec3, kelvin: f32, intensity: f32) -> Self {
+        Self::new(pos, color::kelvin(kelvin) * intensity)
+    }
+
+    // A light with an sRGB color given as "#rrggbb"
+    pub fn from_hex(pos: Vec3, hex: &str, intensity: f32) -> Option<Self> {
+        Some(Self::new(pos, color::hex(hex)? * intensity))
+    }
+
+    // A light infinitely far away in the given direction, such as the sun
+    pub fn directional(dir: Vec3, color: Vec3) -> Self {
+        Self {
+            directional: true,
+            ..Self::new(dir.normalized(), color)
+        }
+    }
+
+    // A spherical light, which gives soft shadows and visible highlights on
+    // glossy surfaces. The color is the irradiance it casts, as for point lights.
+    pub fn area(pos: Vec3, radius: f32, color: Vec3) -> Self {
+        Self {
+            radius,
+            ..Self::new(pos, color)
+        }
+    }
+
+    // Restricts the light to surfaces whose light mask includes one of the
+    // groups, see LightGroups. Lights without groups light everything.
+    pub fn with_groups(self, groups: u32) -> Self {
+        Self { groups, ..self }
+    }
+
+    // Lets some of the light through in shadows, scaled by the tint, for
+    // colored shadows without indirect lighting. Overrides Scene::shadow_tint.
+    pub fn with_shadow_tint(self, tint: Vec3) -> Self {
+        Self {
+            shadow_tint: Some(tint),
+            ..self
+        }
+    }
+
+    // As saved in scene files, with the position or direction depending on
+    // the kind of light
+    pub(crate) fn to_json(self) -> Json {
+        let pos = if self.directional {
+            "direction"
+        } else {
+            "position"
+        };
+        Json::object([
+            (pos, self.pos.into()),
+            ("color", self.color.into()),
+            ("radius", self.radius.into()),
+            ("groups", self.groups.into()),
+            ("shadow_tint", self.shadow_tint.into()),
+        ])
+    }
+
+    pub(crate) fn from_json(json: &Json) -> Result<Self, String> {
+        let (pos, directional) = match json.optional("direction", Json::direction)? {
+            Some(dir) => (dir, true),
+            None => (json.required("position", Json::vec3)?, false),
+        };
+        Ok(Self {
+            pos,
+            color: json.required("color", Json::vec3)?,
+            radius: json.optional("radius", Json::f32)?.unwrap_or(0.),
+            directional,
+            groups: json.optional("groups", Json::u32)?.unwrap_or(u32::MAX),
+            shadow_tint: json.optional("shadow_tint", Json::vec3)?,
+        })
+    }
+
+    pub(crate) fn validate(&self, warnings: &mut Vec<Warning>) {
+        if self.color.component_min() < 0. {
+            warnings.push(Warning::OutOfRange {
+                what: "light color",
+                value: self.color.component_min(),
+            });
+        }
+        if let Some(tint) = self.shadow_tint {
+            check_color(warnings, "shadow tint", tint);
+        }
+        if self.radius < 0. {
+            warnings.push(Warning::EmptyPrimitive {
+                what: "area light",
+                size: self.radius,
+            });
+        }
+    }
+
+    pub(crate) fn affects(&self, surface: &Surface) -> bool {
+        self.groups & surface.light_mask != 0
+    }
+
+    pub(crate) fn in_shadow(&self, scene: &Scene, hit: &Hit, target: Vec3) -> bool {
+        let l = (target - hit.p).normalized();
+        // Step out of object, which is all the shadow there is if that fails
+        let p = match raycast_out(scene, hit.p, l, hit.traveled) {
+            Some(p) => p,
+            None => return true,
+        };
+        // Past the last occupied cell of the occlusion cache, if there is one,
+        // nothing can be in the way
+        let limit = match &scene.occlusion {
+            Some(cache) => match cache.last_occupied(p, l, (target - p).dot(l)) {
+                Some(limit) => Some(limit),
+                None => return false,
+            },
+            None => None,
+        };
+        // Check for any objects while tracing towards the light source
+        raycast(scene, p, l, RayKind::Shadow, hit.traveled, |q| {
+            (target - q).dot(l) > 0. && limit.is_none_or(|limit| (q - p).dot(l) < limit)
+        })
+        .is_some()
+    }
+
+    pub(crate) fn diffuse(&self, p: Vec3, target: Vec3, n: Vec3) -> f32 {
+        let l = (target - p).normalized();
+        n.dot(l).clamp(0.0, 1.0)
+    }
+
+    fn cos_max(&self, p: Vec3) -> Option<f32> {
+        let dist_sq = (self.pos - p).mag_sq();
+        let radius_sq = self.radius * self.radius;
+        if self.radius > 0. && dist_sq > radius_sq {
+            Some((1. - radius_sq / dist_sq).sqrt())
+        } else {
+            None
+        }
+    }
+
+    // Solid angle density of sample_point as seen from p, infinite for points
+    pub(crate) fn pdf(&self, p: Vec3) -> f32 {
+        match self.cos_max(p) {
+            Some(cos_max) => 1. / (2. * PI * (1. - cos_max)),
+            None => f32::INFINITY,
+        }
+    }
+
+    // Picks a point on the light uniformly over the cone it subtends from p
+    pub(crate) fn sample_point(&self, scene: &Scene, p: Vec3, rng: &mut Rng) -> Vec3 {
+        // Directional lights are placed as far as rays go, for shadow rays
+        if self.directional {
+            return p + self.pos * scene.max_distance();
+        }
+        let cos_max = match self.cos_max(p) {
+            Some(cos_max) => cos_max,
+            None => return self.pos,
+        };
+        let w = (self.pos - p).normalized();
+        let cos_theta = 1. - rng.next_f32() * (1. - cos_max);
+        let dir = cone_direction(w, cos_theta, 2. * PI * rng.next_f32());
+        // Project onto the sphere along the sampled direction
+        let to_center = self.pos - p;
+        let along = to_center.dot(dir);
+        let offset_sq = to_center.mag_sq() - along * along;
+        let t = along - (self.radius * self.radius - offset_sq).max(0.).sqrt();
+        p + dir * t
+    }
+
+    // Start and direction of a photon leaving the light, for a point (u, v)
+    // in the unit square. Point and area lights send photons from their
+    // center evenly over all directions, and directional lights send them
+    // from a square as wide as the scene, facing the light.
+    pub(crate) fn emit(&self, scene: &Scene, (u, v): (f32, f32)) -> (Vec3, Vec3) {
+        if self.directional {
+            let (center, reach) = emission_square(scene);
+            let w = -self.pos;
+            let helper = if w.x.abs() > 0.9 {
+                Vec3::unit_y()
+            } else {
+                Vec3::unit_x()
+            };
+            let a = w.cross(helper).normalized();
+            let b = w.cross(a);
+            let origin = center + self.pos * reach + (a * (u - 0.5) + b * (v - 0.5)) * 2. * reach;
+            return (origin, w);
+        }
+        let cos_theta = 1. - 2. * v;
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * u;
+        let dir = Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+        (self.pos, dir)
+    }
+
+    // Light carried by a photon that covers the fraction of the unit square
+    // of emit, landing this far from where it started. Like direct lighting,
+    // point lights don't fall off with distance, so a photon covers the area
+    // its share of directions spreads over where it lands.
+    pub(crate) fn photon_flux(&self, scene: &Scene, fraction: f32, distance: f32) -> Vec3 {
+        let area = if self.directional {
+            let (_, reach) = emission_square(scene);
+            4. * reach * reach
+        } else {
+            4. * PI * distance * distance
+        };
+        self.color * area * fraction
+    }
+
+    // Distance along the ray to the light's surface, if it is hit at all
+    pub(crate) fn intersect(&self, from: Vec3, dir: Vec3) -> Option<f32> {
+        if self.radius <= 0. {
+            return None;
+        }
+        let to_center = self.pos - from;
+        let along = to_center.dot(dir);
+        let offset_sq = to_center.mag_sq() - along * along;
+        let radius_sq = self.radius * self.radius;
+        if along <= 0. || offset_sq > radius_sq {
+            return None;
+        }
+        Some(along - (radius_sq - offset_sq).sqrt())
+    }
+}
+
+// Center and half width of the square that directional lights send photons
+// from, covering the bounds or everything within reach of the origin
+fn emission_square(scene: &Scene) -> (Vec3, f32) {
+    match scene.bounds {
+        Some((min, max)) => ((min + max) * 0.5, (max - min).mag() * 0.5),
+        None => (Vec3::zero(), scene.max_distance() * 0.5),
+    }
+}
+
+// Direction at the given angle from axis w, rotated by phi around it
+pub(crate) fn cone_direction(w: Vec3, cos_theta: f32, phi: f32) -> Vec3 {
+    let helper = if w.x.abs() > 0.9 {
+        Vec3::unit_y()
+    } else {
+        Vec3::unit_x()
+    };
+    let u = w.cross(helper).normalized();
+    let v = w.cross(u);
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    (u * phi.cos() * sin_theta + v * phi.sin() * sin_theta + w * cos_theta).normalized()
+}
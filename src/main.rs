@@ -1,61 +1,791 @@
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use anyhow::Result;
-use image::{ImageBuffer, Rgba, RgbaImage};
+use anyhow::{anyhow, bail, Result};
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Rgb32FImage, RgbaImage};
 use rayon::prelude::*;
 use ultraviolet::Vec3;
 
-use raycast::{raytrace, Light};
+use metadata::Metadata;
 
-fn main() -> Result<()> {
-    let width = 640u32;
-    let height = 480u32;
+use raycast::camera::{Lens, StereoLayout};
+use raycast::caustics::{self, Caustics};
+use raycast::color;
+use raycast::distfield::Surface;
+use raycast::map::{height_map, height_map_rgba};
+use raycast::mipmap::DistanceMipmap;
+use raycast::occlusion::OcclusionCache;
+use raycast::post::{self, Effect, Framebuffer, ToneMap};
+use raycast::prepass::TilePrepass;
+use raycast::script::Script;
+use raycast::{
+    render_pixel, render_pixel_hdr, render_pixel_layers, scenes, ClipPlane, Scene, Settings,
+};
 
-    let eye = Vec3::new(0., 0., -100.);
-    let center = Vec3::new(width as _, height as _, 0.0) * 0.5;
+mod config;
+mod diff;
+mod distributed;
+mod metadata;
+mod serve;
 
-    let mut img: RgbaImage = ImageBuffer::new(width, height);
-    let coords: Vec<_> = img.enumerate_pixels().map(|(x, y, _)| (x, y)).collect();
+// Renders with more pixels than this are encoded while rendering, a band of
+// about BAND_PIXELS at a time, instead of being kept in memory whole
+const STREAM_PIXELS: u64 = 1 << 24;
+const BAND_PIXELS: u32 = 1 << 20;
 
-    let lights = [
-        Light::new(Vec3::new(500., 1000., -300.), Vec3::new(1.0, 0.5, 0.)),
-        Light::new(Vec3::new(-700., -500., -10.), Vec3::new(0., 0.5, 1.0)),
-        Light::new(Vec3::new(-700., 1500., 10.), Vec3::new(0.5, 0., 1.0)),
-        Light::new(Vec3::new(10., -20., -50.), Vec3::new(0.3, 0.2, 0.2)),
-    ];
+// Evaluates every pixel in parallel, in row major order
+fn render_pixels<T, F>(width: u32, height: u32, show_progress: bool, pixel: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(u32, u32) -> T + Sync,
+{
+    let coords: Vec<_> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .collect();
 
-    let progress = Arc::new(Mutex::new((0i32, progress::Bar::new())));
-    let pixels: Vec<_> = coords
+    let progress = Arc::new(Mutex::new((0u64, progress::Bar::new())));
+    coords
         .par_iter()
         .map_with(progress, |progress, (x, y)| {
-            {
+            if show_progress {
                 let mut progress = progress.lock().unwrap();
                 let (ref mut num, ref mut bar) = *progress;
                 *num += 1;
                 if *num % 16 == 0 {
-                    bar.reach_percent(*num * 100 / (width * height) as i32);
+                    bar.reach_percent((*num * 100 / (width as u64 * height as u64)) as i32);
                 }
             }
 
-            let p_img = Vec3::new(*x as _, (height - *y) as _, 0.0);
-            let p_scaled = (p_img - center) / width.min(height) as f32 * 250.;
-            let ray_dir = (p_scaled - eye).normalized();
+            pixel(*x, *y)
+        })
+        .collect()
+}
 
-            let color = raytrace(eye, ray_dir, &lights, 5).map(|rgb| {
-                let rgb_scaled = rgb * 255.;
-                Rgba([rgb_scaled.x as _, rgb_scaled.y as _, rgb_scaled.z as _, 255])
-            });
+fn render(
+    scene: &Scene,
+    settings: &Settings,
+    width: u32,
+    height: u32,
+    show_progress: bool,
+) -> RgbaImage {
+    let pixels = render_pixels(width, height, show_progress, |x, y| {
+        render_pixel(scene, settings, width, height, x, y)
+    });
+    ImageBuffer::from_raw(width, height, pixels.concat()).unwrap()
+}
+
+// Renders unclamped colors, for post processing
+fn render_hdr(
+    scene: &Scene,
+    settings: &Settings,
+    width: u32,
+    height: u32,
+    show_progress: bool,
+) -> Framebuffer {
+    let pixels = render_pixels(width, height, show_progress, |x, y| {
+        render_pixel_hdr(scene, settings, width, height, x, y)
+    });
+    let (colors, coverage) = pixels.into_iter().unzip();
+    Framebuffer {
+        width,
+        height,
+        colors,
+        coverage,
+    }
+}
+
+// Renders the image along with one layer per light, and one for the rest
+fn render_layers(
+    scene: &Scene,
+    settings: &Settings,
+    width: u32,
+    height: u32,
+) -> (RgbaImage, Vec<Rgb32FImage>) {
+    let pixels = render_pixels(width, height, true, |x, y| {
+        render_pixel_layers(scene, settings, width, height, x, y)
+    });
+    let rgba: Vec<u8> = pixels.iter().flat_map(|(rgba, _)| *rgba).collect();
+    let layers = (0..=scene.lights.len())
+        .map(|i| {
+            let layer = pixels
+                .iter()
+                .flat_map(|(_, layers)| [layers[i].x, layers[i].y, layers[i].z])
+                .collect();
+            ImageBuffer::from_raw(width, height, layer).unwrap()
+        })
+        .collect();
+    (ImageBuffer::from_raw(width, height, rgba).unwrap(), layers)
+}
+
+// Renders a band of rows at a time, passing the RGBA pixels of each to
+// write as soon as it completes, so only one band is kept in memory
+fn render_bands<F>(
+    scene: &Scene,
+    settings: &Settings,
+    (width, height): (u32, u32),
+    band: u32,
+    show_progress: bool,
+    mut write: F,
+) -> Result<()>
+where
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    let mut bar = progress::Bar::new();
+    for y0 in (0..height).step_by(band as usize) {
+        let rows: Vec<u8> = (y0..(y0 + band).min(height))
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .collect::<Vec<_>>()
+            .par_iter()
+            .flat_map_iter(|&(x, y)| render_pixel(scene, settings, width, height, x, y))
+            .collect();
+        write(&rows)?;
+        if show_progress {
+            bar.reach_percent(((y0 + band).min(height) * 100 / height) as i32);
+        }
+    }
+    Ok(())
+}
+
+fn stream(
+    scene: &Scene,
+    settings: &Settings,
+    width: u32,
+    height: u32,
+    out: &mut impl Write,
+) -> Result<()> {
+    // Binary PPM, written a band of scanlines at a time as they complete
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    render_bands(scene, settings, (width, height), 8, false, |rows| {
+        let rgb: Vec<u8> = rows
+            .chunks(4)
+            .flat_map(|rgba| [rgba[0], rgba[1], rgba[2]])
+            .collect();
+        out.write_all(&rgb)?;
+        out.flush()?;
+        Ok(())
+    })
+}
+
+// Renders straight into the PNG, see STREAM_PIXELS
+fn render_png_streamed(
+    scene: &Scene,
+    settings: &Settings,
+    (width, height): (u32, u32),
+    path: &str,
+    metadata: &Metadata,
+) -> Result<()> {
+    let mut writer = metadata.png_writer(path, (width, height))?;
+    let mut out = writer.stream_writer()?;
+    let band = (BAND_PIXELS / width).max(1);
+    render_bands(scene, settings, (width, height), band, true, |rows| {
+        Ok(out.write_all(rows)?)
+    })?;
+    out.finish()?;
+    Ok(())
+}
+
+enum Command {
+    Render,
+    Bench,
+    Serve(String),
+    Coordinate(String),
+    Worker(String),
+    Map(Vec3, Vec3),
+    Diff(String, String),
+    // Saves the scene, with the scene options applied, to a scene file.
+    // Only fields built from graph nodes that compile can be saved, which
+    // of the built-in scenes are graph and generated, and scene files.
+    Export(String),
+}
+
+struct Options {
+    command: Command,
+    scene: String,
+    script: Option<String>,
+    // Scene saved with --export, instead of a built-in one
+    scene_file: Option<String>,
+    settings: Settings,
+    preview: bool,
+    stream: bool,
+    light_layers: bool,
+    post: Vec<Effect>,
+    clip_planes: Vec<ClipPlane>,
+    shadow_tint: Option<Vec3>,
+    gradient_sky: bool,
+    unit_scale: Option<f32>,
+    world_bounds: Option<(Vec3, Vec3)>,
+    // Number of photons to trace for caustics, and the radius to gather
+    // them over if not the default
+    caustics: Option<(u32, Option<f32>)>,
+    // Cells along each axis of the occlusion cache for shadow rays
+    occlusion_cache: Option<u32>,
+    // Cells along each axis of the finest level of the distance mipmap for
+    // skipping empty space
+    empty_space: Option<u32>,
+    // Size of the tiles that share where camera rays start marching
+    tile_prepass: Option<u32>,
+    // Size of the image, or of each view for stereo
+    resolution: Option<(u32, u32)>,
+    // Where to save the image, as PNG or EXR depending on the extension
+    output: String,
+    threads: Option<usize>,
+    low_priority: bool,
+    tone_map: ToneMap,
+}
+
+fn value<T>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let arg = args
+        .next()
+        .ok_or_else(|| anyhow!("{} requires a value", flag))?;
+    arg.parse()
+        .map_err(|err| anyhow!("invalid value for {}: {}", flag, err))
+}
+
+impl Options {
+    fn parse() -> Result<Self> {
+        let mut options = Self {
+            command: Command::Render,
+            scene: "default".into(),
+            script: None,
+            scene_file: None,
+            settings: Settings::default(),
+            preview: false,
+            stream: false,
+            light_layers: false,
+            post: Vec::new(),
+            clip_planes: Vec::new(),
+            shadow_tint: None,
+            gradient_sky: false,
+            unit_scale: None,
+            world_bounds: None,
+            caustics: None,
+            occlusion_cache: None,
+            empty_space: None,
+            tile_prepass: None,
+            resolution: None,
+            output: "test.png".into(),
+            threads: None,
+            low_priority: false,
+            tone_map: ToneMap::Clamp,
+        };
+        let mut args = env::args().skip(1).peekable();
+        if args.next_if(|arg| arg == "diff").is_some() {
+            let mut path = || {
+                args.next_if(|arg| !arg.starts_with("--"))
+                    .ok_or_else(|| anyhow!("diff requires two images"))
+            };
+            options.command = Command::Diff(path()?, path()?);
+        }
+        let command = args.next_if(|arg| ["serve", "coordinate", "worker"].contains(&arg.as_str()));
+        if let Some(command) = command {
+            let addr = args
+                .next_if(|arg| !arg.starts_with("--"))
+                .unwrap_or_else(|| "127.0.0.1:8080".into());
+            options.command = match command.as_str() {
+                "serve" => Command::Serve(addr),
+                "coordinate" => Command::Coordinate(addr),
+                _ => Command::Worker(addr),
+            };
+        }
+        // The config file comes first, so that the command line overrides it
+        if let Some(entries) = config::load(config::PATH)? {
+            for entry in entries {
+                let mut args = entry.to_args().into_iter();
+                if let Some(arg) = args.next() {
+                    options
+                        .flag(&arg, &mut args)
+                        .map_err(|err| anyhow!("{}: line {}: {}", config::PATH, entry.line, err))?;
+                }
+            }
+        }
+        while let Some(arg) = args.next() {
+            options.flag(&arg, &mut args)?;
+        }
+        if options.settings.samples == 0 {
+            bail!("--samples must be at least 1");
+        }
+        // Workers only receive the scene name and settings in the TILE
+        // message and send back 8 bit tiles, so refuse anything else that
+        // would change the render rather than quietly leave it out
+        if matches!(options.command, Command::Coordinate(_)) {
+            let local_only: Vec<&str> = [
+                ("--script", options.script.is_some()),
+                ("--scene-file", options.scene_file.is_some()),
+                ("--clip", !options.clip_planes.is_empty()),
+                ("--shadow-tint", options.shadow_tint.is_some()),
+                ("--sky", options.gradient_sky),
+                ("--unit-scale", options.unit_scale.is_some()),
+                ("--world-bounds", options.world_bounds.is_some()),
+                ("--caustics", options.caustics.is_some()),
+                ("--occlusion-cache", options.occlusion_cache.is_some()),
+                ("--empty-space", options.empty_space.is_some()),
+                ("--tile-prepass", options.tile_prepass.is_some()),
+                ("--post", !options.post.is_empty()),
+                ("--tone-map", options.tone_map != ToneMap::Clamp),
+                ("--preview", options.preview),
+                ("--stream", options.stream),
+                ("--light-layers", options.light_layers),
+            ]
+            .into_iter()
+            .filter_map(|(flag, set)| set.then_some(flag))
+            .collect();
+            if !local_only.is_empty() {
+                bail!(
+                    "{} can't be used when distributing a render",
+                    local_only.join(", ")
+                );
+            }
+        }
+        let hdr = !options.post.is_empty() || options.tone_map != ToneMap::Clamp;
+        if hdr && (options.stream || options.light_layers) {
+            bail!("--post and --tone-map can't be combined with --stream or --light-layers");
+        }
+        Ok(options)
+    }
+
+    fn flag(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> Result<()> {
+        match arg {
+            "--bench" => self.command = Command::Bench,
+            "--map" => {
+                let bounds: String = value(args, arg)?;
+                let (min, max) = parse_bounds(&bounds)
+                    .ok_or_else(|| anyhow!("--map requires min and max x,y,z bounds"))?;
+                self.command = Command::Map(min, max);
+            }
+            "--export" => self.command = Command::Export(value(args, arg)?),
+            "--preview" => self.preview = true,
+            "--stream" => self.stream = true,
+            "--light-layers" => self.light_layers = true,
+            "--scene" => self.scene = value(args, arg)?,
+            "--script" => self.script = Some(value(args, arg)?),
+            "--scene-file" => self.scene_file = Some(value(args, arg)?),
+            "--samples" => self.settings.samples = value(args, arg)?,
+            "--seed" => self.settings.seed = value(args, arg)?,
+            "--max-bounces" => self.settings.max_bounces = value(args, arg)?,
+            "--roulette" => self.settings.roulette = true,
+            "--normal-offset" => self.settings.reflection_offset = Some(value(args, arg)?),
+            "--lens" => self.settings.lens = value(args, arg)?,
+            "--resolution" => {
+                let resolution: String = value(args, arg)?;
+                self.resolution = Some(
+                    parse_resolution(&resolution)
+                        .ok_or_else(|| anyhow!("--resolution requires a size like 1280x720"))?,
+                );
+            }
+            "--output" => self.output = value(args, arg)?,
+            "--threads" => self.threads = Some(value(args, arg)?),
+            "--low-priority" => self.low_priority = true,
+            "--tone-map" => self.tone_map = value(args, arg)?,
+            "--clip" => {
+                let plane: String = value(args, arg)?;
+                let plane = match parse_floats(&plane).as_deref() {
+                    Some(&[x, y, z, offset]) => ClipPlane::new(Vec3::new(x, y, z), offset),
+                    _ => bail!("--clip requires a normal x,y,z and offset"),
+                };
+                self.clip_planes.push(plane);
+            }
+            "--clip-cap" => {
+                let color: String = value(args, arg)?;
+                let cap = match parse_color(&color) {
+                    Some(color) => Surface::new(color, 0.),
+                    None => bail!("--clip-cap requires an r,g,b or #rrggbb color"),
+                };
+                // Applies to the planes given so far
+                for plane in &mut self.clip_planes {
+                    plane.cap = Some(cap);
+                }
+            }
+            "--shadow-tint" => {
+                let tint: String = value(args, arg)?;
+                self.shadow_tint = match parse_color(&tint) {
+                    Some(tint) => Some(tint),
+                    None => bail!("--shadow-tint requires an r,g,b or #rrggbb color"),
+                };
+            }
+            "--sky" => match value::<String>(args, arg)?.as_str() {
+                "gradient" => self.gradient_sky = true,
+                sky => bail!("unknown sky: {}", sky),
+            },
+            "--unit-scale" => match value(args, arg)? {
+                scale if scale > 0. => self.unit_scale = Some(scale),
+                _ => bail!("--unit-scale must be positive"),
+            },
+            "--world-bounds" => {
+                let bounds: String = value(args, arg)?;
+                self.world_bounds =
+                    Some(parse_bounds(&bounds).ok_or_else(|| {
+                        anyhow!("--world-bounds requires min and max x,y,z bounds")
+                    })?);
+            }
+            "--caustics" => {
+                let caustics: String = value(args, arg)?;
+                self.caustics = Some(parse_caustics(&caustics).ok_or_else(|| {
+                    anyhow!("--caustics requires a photon count, and optionally :radius")
+                })?);
+            }
+            "--occlusion-cache" => match value(args, arg)? {
+                0 => bail!("--occlusion-cache requires at least 1 cell"),
+                cells => self.occlusion_cache = Some(cells),
+            },
+            "--empty-space" => match value(args, arg)? {
+                0 => bail!("--empty-space requires at least 1 cell"),
+                cells => self.empty_space = Some(cells),
+            },
+            "--tile-prepass" => match value(args, arg)? {
+                0 => bail!("--tile-prepass requires a tile size of at least 1"),
+                tile => self.tile_prepass = Some(tile),
+            },
+            "--toon" => self.settings.toon = Some(value(args, arg)?),
+            "--dispersion" => match value(args, arg)? {
+                0 => bail!("--dispersion requires at least 1 wavelength"),
+                count => self.settings.dispersion = Some(count),
+            },
+            "--stereo" => self.settings.stereo = Some(value(args, arg)?),
+            "--ipd" => match &mut self.settings.stereo {
+                Some(stereo) => stereo.ipd = value(args, arg)?,
+                None => bail!("--ipd requires --stereo first"),
+            },
+            "--post" => {
+                let effects: String = value(args, arg)?;
+                for effect in effects.split(',') {
+                    self.post
+                        .push(effect.parse().map_err(|err| anyhow!("{}", err))?);
+                }
+            }
+            _ => bail!("unknown argument: {}", arg),
+        }
+        Ok(())
+    }
+}
+
+// Parses comma separated numbers
+fn parse_floats(s: &str) -> Option<Vec<f32>> {
+    s.split(',').map(|v| v.trim().parse().ok()).collect()
+}
+
+// Parses linear "r,g,b", or sRGB "#rrggbb"
+fn parse_color(s: &str) -> Option<Vec3> {
+    if s.starts_with('#') {
+        return color::hex(s);
+    }
+    match parse_floats(s)?[..] {
+        [r, g, b] => Some(Vec3::new(r, g, b)),
+        _ => None,
+    }
+}
+
+// Parses "<photons>" or "<photons>:<radius>"
+fn parse_caustics(s: &str) -> Option<(u32, Option<f32>)> {
+    let (photons, radius) = match s.split_once(':') {
+        Some((photons, radius)) => (photons, Some(radius.parse().ok()?)),
+        None => (s, None),
+    };
+    match (photons.parse().ok()?, radius) {
+        (0, _) => None,
+        (_, Some(radius)) if radius <= 0. => None,
+        caustics => Some(caustics),
+    }
+}
+
+// Parses "<width>x<height>"
+fn parse_resolution(s: &str) -> Option<(u32, u32)> {
+    let (width, height) = s.split_once('x')?;
+    match (width.parse().ok()?, height.parse().ok()?) {
+        (0, _) | (_, 0) => None,
+        resolution => Some(resolution),
+    }
+}
+
+// Parses "min_x,min_y,min_z,max_x,max_y,max_z"
+fn parse_bounds(s: &str) -> Option<(Vec3, Vec3)> {
+    match parse_floats(s)?[..] {
+        [x0, y0, z0, x1, y1, z1] if x0 < x1 && y0 < y1 && z0 < z1 => {
+            Some((Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1)))
+        }
+        _ => None,
+    }
+}
+
+// Top-down height map of the bounds, 512 pixels along the longer side
+fn map(scene: &Scene, min: Vec3, max: Vec3) -> RgbaImage {
+    let size = max - min;
+    let scale = 512. / size.x.max(size.z);
+    let width = ((size.x * scale).round() as u32).max(1);
+    let height = ((size.z * scale).round() as u32).max(1);
+    let heights = height_map(scene, (min, max), width, height);
+    ImageBuffer::from_raw(width, height, height_map_rgba(&heights, (min, max))).unwrap()
+}
+
+// Compares two renders, writing the difference as an image and a heat map
+fn diff(a: &str, b: &str) -> Result<()> {
+    let open = |path: &str| -> Result<_> {
+        let img = image::open(path).map_err(|err| anyhow!("{}: {}", path, err))?;
+        Ok(img.into_rgba32f())
+    };
+    let (a, b) = (open(a)?, open(b)?);
+    let difference = diff::absolute(&a, &b)?;
+    let metrics = diff::metrics(&a, &b, &difference);
+    println!("rmse {:.6}", metrics.rmse);
+    println!("ssim {:.6}", metrics.ssim);
+    println!("max  {:.6}", metrics.max);
+    difference.save("diff.exr")?;
+    Ok(diff::heat_map(&difference).save("diff.png")?)
+}
+
+fn is_exr(path: &str) -> bool {
+    path.to_lowercase().ends_with(".exr")
+}
 
-            color.unwrap_or(Rgba([0, 0, 0, 0]))
+// EXR next to the output, named after it, such as out_light0.exr for out.png
+fn layer_path(output: &str, layer: &str) -> PathBuf {
+    let output = Path::new(output);
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{}_{}.exr", stem, layer))
+}
+
+// Saves as 8 bit PNG, or as floats for EXR
+fn save(img: RgbaImage, path: &str, metadata: &Metadata) -> Result<()> {
+    if !is_exr(path) {
+        return metadata.save_png(&img, path);
+    }
+    let pixels: Vec<f32> = img
+        .pixels()
+        .flat_map(|p| {
+            let alpha = p[3] as f32 / 255.;
+            let [r, g, b] = [0, 1, 2].map(|i| p[i] as f32 / 255. * alpha);
+            [r, g, b, alpha]
         })
         .collect();
+    metadata.save_exr(img.dimensions(), &pixels, path)
+}
+
+fn bench(settings: &Settings) {
+    let (width, height) = (320, 240);
+    let time = |name: &str, scene: &Scene| {
+        let start = Instant::now();
+        render(scene, settings, width, height, false);
+        let elapsed = start.elapsed().as_secs_f64();
+        println!("{:<16} {:>8.3}s", name, elapsed);
+        elapsed
+    };
+    let mut total = 0.;
+    for (name, scene) in scenes::bench() {
+        total += time(name, &scene);
+    }
+    println!("{:<16} {:>8.3}s", "total", total);
+    // Not in the total, which only covers the original scenes
+    println!();
+    for (name, scene) in scenes::bench_extra() {
+        time(name, &scene);
+    }
+}
+
+// Lets other programs go first, for rendering in the background. Threads
+// started afterwards inherit the priority.
+#[cfg(unix)]
+fn lower_priority() -> Result<()> {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn lower_priority() -> Result<()> {
+    bail!("--low-priority is only supported on unix")
+}
+
+fn main() -> Result<()> {
+    let options = Options::parse()?;
+    if options.low_priority {
+        lower_priority()?;
+    }
+    // Zero threads uses one for each core
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads.unwrap_or(0))
+        .build()?;
+    pool.install(|| run(options))
+}
+
+fn run(options: Options) -> Result<()> {
+    // Commands that don't render the chosen scene, so needn't build it
+    match &options.command {
+        Command::Diff(a, b) => return diff(a, b),
+        Command::Bench => {
+            bench(&options.settings);
+            return Ok(());
+        }
+        Command::Serve(addr) => return serve::serve(addr),
+        Command::Worker(addr) => return distributed::work(addr),
+        _ => {}
+    }
+    let (mut width, mut height) = match (options.resolution, options.settings.lens) {
+        (Some(resolution), _) => resolution,
+        // Panoramas cover twice the angle across as they do vertically
+        (None, Lens::Equirectangular) => (960, 480),
+        (None, _) => (640, 480),
+    };
+    // Stereo frames hold a full size view for each eye
+    match options.settings.stereo.map(|stereo| stereo.layout) {
+        Some(StereoLayout::SideBySide) => width *= 2,
+        Some(StereoLayout::TopBottom) => height *= 2,
+        None => {}
+    }
+    let mut scene = match (&options.script, &options.scene_file) {
+        (Some(path), _) => {
+            let script = Script::parse(&fs::read_to_string(path)?)?;
+            Scene::new(script, scenes::default_lights())
+        }
+        (None, Some(path)) => Scene::load(path).map_err(|err| anyhow!("{}: {}", path, err))?,
+        (None, None) => match scenes::by_name(&options.scene) {
+            Some(scene) => scene,
+            None => bail!("unknown scene: {}", options.scene),
+        },
+    };
+    scene.clip_planes.extend(&options.clip_planes);
+    if options.shadow_tint.is_some() {
+        scene.shadow_tint = options.shadow_tint;
+    }
+    if options.gradient_sky {
+        scene.sky = Some(scenes::gradient_sky());
+    }
+    if let Some(scale) = options.unit_scale {
+        scene.unit_scale = scale;
+    }
+    if options.world_bounds.is_some() {
+        scene.bounds = options.world_bounds;
+    }
+    if let Some((photons, radius)) = options.caustics {
+        let radius = radius.unwrap_or(caustics::DEFAULT_RADIUS * scene.unit_scale);
+        let traced = Caustics::trace(&scene, photons, radius, options.settings.seed);
+        eprintln!("caustics: stored {} photons", traced.len());
+        scene.caustics = Some(traced);
+    }
+    if let Some(cells) = options.occlusion_cache {
+        let bounds = scene
+            .bounds
+            .ok_or_else(|| anyhow!("--occlusion-cache requires --world-bounds"))?;
+        let cache = OcclusionCache::build(&scene, bounds, cells);
+        eprintln!(
+            "occlusion cache: {:.1}% of cells occupied",
+            cache.coverage() * 100.
+        );
+        scene.occlusion = Some(cache);
+    }
+    if let Some(cells) = options.empty_space {
+        let bounds = scene
+            .bounds
+            .ok_or_else(|| anyhow!("--empty-space requires --world-bounds"))?;
+        scene.empty_space = Some(DistanceMipmap::build(&scene, bounds, cells));
+    }
+    if let Some(tile) = options.tile_prepass {
+        if scene.bounds.is_none() {
+            bail!("--tile-prepass requires --world-bounds");
+        }
+        let prepass = TilePrepass::build(&scene, &options.settings, (width, height), tile);
+        eprintln!(
+            "tile prepass: rays start {:.1} along on average",
+            prepass.mean_start()
+        );
+        scene.prepass = Some(prepass);
+    }
+    for warning in scene.validate() {
+        eprintln!("warning: {}", warning);
+    }
+    let scene_name = options
+        .script
+        .as_ref()
+        .or(options.scene_file.as_ref())
+        .unwrap_or(&options.scene);
+    let metadata = Metadata::new(scene_name, &scene, &options.settings, (width, height));
+    match &options.command {
+        Command::Render => {}
+        Command::Coordinate(addr) => {
+            let img =
+                distributed::coordinate(addr, &options.scene, &options.settings, width, height)?;
+            return save(img, &options.output, &metadata);
+        }
+        Command::Map(min, max) => {
+            let img = map(&scene, *min, *max);
+            let metadata = Metadata::for_map(scene_name, &scene, (*min, *max), img.dimensions());
+            return save(img, &options.output, &metadata);
+        }
+        Command::Export(path) => {
+            return scene.save(path).map_err(|err| match err.kind() {
+                io::ErrorKind::Unsupported => anyhow!(
+                    "can't export to {}: {}; only scenes built from graph nodes, \
+                     such as graph and generated, can be exported",
+                    path,
+                    err
+                ),
+                _ => anyhow!("can't export to {}: {}", path, err),
+            });
+        }
+        Command::Diff(..) | Command::Bench | Command::Serve(_) | Command::Worker(_) => {
+            unreachable!("handled before building the scene")
+        }
+    }
+
+    let settings = &options.settings;
+    if options.stream {
+        return stream(&scene, settings, width, height, &mut io::stdout().lock());
+    }
+
+    if options.preview {
+        // Progressively refine so a bad composition can be spotted early
+        for factor in [8, 4, 2] {
+            let preview = render(&scene, settings, width / factor, height / factor, false);
+            let preview = imageops::resize(&preview, width, height, FilterType::Nearest);
+            save(preview, &options.output, &metadata)?;
+            println!("saved 1/{} resolution preview", factor);
+        }
+    }
+
+    if options.light_layers {
+        let (img, layers) = render_layers(&scene, settings, width, height);
+        for (i, layer) in layers.iter().enumerate() {
+            if i < scene.lights.len() {
+                layer.save(layer_path(&options.output, &format!("light{}", i)))?;
+            } else {
+                layer.save(layer_path(&options.output, "other"))?;
+            }
+        }
+        return save(img, &options.output, &metadata);
+    }
 
-    img.enumerate_pixels_mut()
-        .map(|(_, _, pixel)| pixel)
-        .zip(pixels)
-        .for_each(|(pixel, color)| {
-            *pixel = color;
-        });
+    let exr = is_exr(&options.output);
+    if exr || !options.post.is_empty() || options.tone_map != ToneMap::Clamp {
+        let mut fb = render_hdr(&scene, settings, width, height, true);
+        post::apply(&options.post, &mut fb);
+        // EXR keeps the full range, so it is saved before tone mapping
+        if exr {
+            return metadata.save_exr((width, height), &fb.to_rgba_f32(), &options.output);
+        }
+        options.tone_map.apply(&mut fb);
+        let img = RgbaImage::from_raw(width, height, fb.to_rgba()).unwrap();
+        return save(img, &options.output, &metadata);
+    }
 
-    Ok(img.save("test.png")?)
+    if width as u64 * height as u64 > STREAM_PIXELS {
+        return render_png_streamed(
+            &scene,
+            settings,
+            (width, height),
+            &options.output,
+            &metadata,
+        );
+    }
+    let img = render(&scene, settings, width, height, true);
+    save(img, &options.output, &metadata)
 }
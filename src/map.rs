@@ -0,0 +1,51 @@
+use ultraviolet::Vec3;
+
+use crate::{raycast, RayKind, Scene};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// Height of the topmost surface at each cell of a grid spanning the bounds
+// in x and z, looking straight down from the top of the bounds. Rows go from
+// the far (max z) to the near edge, and columns from min x to max x. Cells
+// where nothing is found above the bottom of the bounds are None.
+pub fn height_map(
+    scene: &Scene,
+    (min, max): (Vec3, Vec3),
+    width: u32,
+    height: u32,
+) -> Vec<Option<f32>> {
+    let down = Vec3::new(0., -1., 0.);
+    let cell = |i: u32| {
+        let (x, y) = (i % width, i / width);
+        let fx = (x as f32 + 0.5) / width as f32;
+        let fz = (y as f32 + 0.5) / height as f32;
+        let from = Vec3::new(
+            min.x + (max.x - min.x) * fx,
+            max.y,
+            max.z - (max.z - min.z) * fz,
+        );
+        raycast(scene, from, down, RayKind::Camera, 0., |p| p.y >= min.y).map(|(_, p)| p.y)
+    };
+    #[cfg(feature = "parallel")]
+    let cells = (0..width * height).into_par_iter().map(cell);
+    #[cfg(not(feature = "parallel"))]
+    let cells = (0..width * height).map(cell);
+    cells.collect()
+}
+
+// Grayscale image of a height map, from black at the bottom of the bounds
+// to white at the top, and transparent where unoccupied
+pub fn height_map_rgba(heights: &[Option<f32>], (min, max): (Vec3, Vec3)) -> Vec<u8> {
+    heights
+        .iter()
+        .flat_map(|h| match h {
+            Some(h) => {
+                let v = ((h - min.y) / (max.y - min.y)).clamp(0., 1.);
+                let v = (v * 255.) as u8;
+                [v, v, v, 255]
+            }
+            None => [0, 0, 0, 0],
+        })
+        .collect()
+}
@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use anyhow::Result;
+use exr::prelude::{AttributeValue, Image, SpecificChannels, Text, Vec2, WritableImage};
+use image::RgbaImage;
+use ultraviolet::Vec3;
+
+use raycast::{Scene, Settings};
+
+// Text entries describing how a render was made, stored in PNG tEXt chunks
+// and EXR header attributes
+pub struct Metadata {
+    entries: Vec<(&'static str, String)>,
+}
+
+impl Metadata {
+    pub fn new(scene_name: &str, scene: &Scene, settings: &Settings, size: (u32, u32)) -> Self {
+        let mut camera = format!("{} {}x{}", settings.lens, size.0, size.1);
+        if let Some(stereo) = settings.stereo {
+            camera += &format!(" {}", stereo);
+        }
+        Self {
+            entries: vec![
+                ("Software", format!("raycast {}", env!("CARGO_PKG_VERSION"))),
+                ("Scene", scene_name.into()),
+                ("Scene hash", format!("{:016x}", scene.fingerprint())),
+                ("Camera", camera),
+                ("Samples", settings.samples.to_string()),
+                ("Seed", settings.seed.to_string()),
+                ("Max bounces", settings.max_bounces.to_string()),
+                ("Roulette", settings.roulette.to_string()),
+                (
+                    "Reflection offset",
+                    settings
+                        .reflection_offset
+                        .map_or_else(|| "march".to_string(), |offset| offset.to_string()),
+                ),
+                (
+                    "Toon",
+                    settings
+                        .toon
+                        .map_or_else(|| "off".to_string(), |toon| toon.to_string()),
+                ),
+                (
+                    "Dispersion",
+                    settings
+                        .dispersion
+                        .map_or_else(|| "off".to_string(), |count| count.to_string()),
+                ),
+            ],
+        }
+    }
+
+    // For height maps, which show the bounds from above instead of what the
+    // camera sees
+    pub fn for_map(
+        scene_name: &str,
+        scene: &Scene,
+        (min, max): (Vec3, Vec3),
+        (width, height): (u32, u32),
+    ) -> Self {
+        Self {
+            entries: vec![
+                ("Software", format!("raycast {}", env!("CARGO_PKG_VERSION"))),
+                ("Scene", scene_name.into()),
+                ("Scene hash", format!("{:016x}", scene.fingerprint())),
+                // As given to --map
+                (
+                    "Map bounds",
+                    format!(
+                        "{},{},{},{},{},{}",
+                        min.x, min.y, min.z, max.x, max.y, max.z
+                    ),
+                ),
+                ("Resolution", format!("{}x{}", width, height)),
+            ],
+        }
+    }
+
+    pub fn save_png(&self, img: &RgbaImage, path: &str) -> Result<()> {
+        let mut writer = self.png_writer(path, img.dimensions())?;
+        writer.write_image_data(img.as_raw())?;
+        Ok(())
+    }
+
+    // Writer for an 8 bit RGBA PNG, with the metadata already written, for
+    // writing the image in parts with stream_writer
+    pub fn png_writer(
+        &self,
+        path: &str,
+        (width, height): (u32, u32),
+    ) -> Result<png::Writer<BufWriter<File>>> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        for (key, value) in &self.entries {
+            encoder.add_text_chunk(key.to_string(), value.clone())?;
+        }
+        Ok(encoder.write_header()?)
+    }
+
+    // Saves RGBA floats, premultiplied as EXR expects
+    pub fn save_exr(&self, (width, height): (u32, u32), pixels: &[f32], path: &str) -> Result<()> {
+        let channels = SpecificChannels::rgba(|pos: Vec2<usize>| {
+            let i = (pos.y() * width as usize + pos.x()) * 4;
+            (pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3])
+        });
+        let mut image = Image::from_channels((width as usize, height as usize), channels);
+        for (key, value) in &self.entries {
+            image.attributes.other.insert(
+                Text::from(*key),
+                AttributeValue::Text(Text::from(value.as_str())),
+            );
+        }
+        image.write().to_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use ultraviolet::Vec3;
+
+use crate::Scene;
+
+struct Level {
+    cells: u32,
+    // Lowest distance to a surface anywhere in each cell
+    distances: Vec<f32>,
+}
+
+// Grids of the smallest distance to a surface within each of their cells,
+// over the world bounds, from fine cells up to a single one. Rays in empty
+// cells jump straight past them without sampling the field, using the
+// largest empty cell they are in. Everything is assumed to be within the
+// bounds, as for OcclusionCache.
+pub struct DistanceMipmap {
+    min: Vec3,
+    size: Vec3,
+    diagonal: f32,
+    // From the finest level to the coarsest, each with half the cells along
+    // each side of the one before
+    levels: Vec<Level>,
+}
+
+impl DistanceMipmap {
+    // Samples the field at the center of each of the finest cells, rounded
+    // up to a power of two along each axis. Their lowest distance is taken
+    // as that less half their diagonal, which is conservative for fields
+    // that don't overestimate distances.
+    pub fn build(scene: &Scene, (min, max): (Vec3, Vec3), cells: u32) -> Self {
+        let cells = cells.max(1).next_power_of_two();
+        let size = max - min;
+        let cell = size / cells as f32;
+        let reach = cell.mag() * 0.5;
+        let distances = (0..cells * cells * cells)
+            .map(|i| {
+                let index = Vec3::new(
+                    (i % cells) as f32,
+                    (i / cells % cells) as f32,
+                    (i / cells / cells) as f32,
+                );
+                scene
+                    .sample(min + (index + Vec3::broadcast(0.5)) * cell)
+                    .distance
+                    - reach
+            })
+            .collect();
+        let mut levels = vec![Level { cells, distances }];
+        while let Some(finer) = levels.last().filter(|level| level.cells > 1) {
+            levels.push(finer.halved());
+        }
+        Self {
+            min,
+            size,
+            diagonal: cell.mag(),
+            levels,
+        }
+    }
+
+    // Size of the finest cells, further than which from any surface a point
+    // is always in an empty one
+    pub(crate) fn diagonal(&self) -> f32 {
+        self.diagonal
+    }
+
+    // Distance the ray can safely go from p without meeting a surface, past
+    // the largest empty cell containing p, or None if p is in an occupied
+    // cell of the finest level or outside the grid
+    pub(crate) fn skip(&self, p: Vec3, dir: Vec3) -> Option<f32> {
+        let u = (p - self.min) / self.size;
+        if u.component_min() < 0. || u.component_max() >= 1. {
+            return None;
+        }
+        self.levels.iter().rev().find_map(|level| {
+            let n = level.cells as f32;
+            let index = [u.x, u.y, u.z].map(|u| ((u * n) as u32).min(level.cells - 1));
+            let distance = level.distance(index);
+            if distance <= 0. {
+                return None;
+            }
+            // Where the ray leaves the cell, past which it is still at least
+            // the distance away from any surface
+            let cell = self.size / n;
+            let exit = (0..3)
+                .map(|axis| {
+                    let low = self.min[axis] + index[axis] as f32 * cell[axis];
+                    let boundary = if dir[axis] > 0. {
+                        low + cell[axis]
+                    } else {
+                        low
+                    };
+                    (boundary - p[axis]) / dir[axis]
+                })
+                .fold(f32::INFINITY, f32::min);
+            Some(exit.max(0.) + distance)
+        })
+    }
+}
+
+impl Level {
+    fn distance(&self, [x, y, z]: [u32; 3]) -> f32 {
+        self.distances[(x + self.cells * (y + self.cells * z)) as usize]
+    }
+
+    // Next coarser level, with the lowest distance of the eight cells each
+    // cell covers
+    fn halved(&self) -> Self {
+        let cells = self.cells / 2;
+        let distances = (0..cells * cells * cells)
+            .map(|i| {
+                let [x, y, z] = [i % cells, i / cells % cells, i / cells / cells];
+                (0..8)
+                    .map(|corner| {
+                        self.distance([
+                            2 * x + (corner & 1),
+                            2 * y + (corner >> 1 & 1),
+                            2 * z + (corner >> 2),
+                        ])
+                    })
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .collect();
+        Self { cells, distances }
+    }
+}
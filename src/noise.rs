@@ -0,0 +1,64 @@
+use ultraviolet::Vec3;
+
+// Hash of a lattice point to [-1, 1]
+fn hash(x: i32, y: i32, z: i32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    h as f32 / u32::MAX as f32 * 2. - 1.
+}
+
+// Quintic, so that gradients are smooth across cells too
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Smoothly interpolated value noise in [-1, 1], varying over unit distances
+pub fn value(p: Vec3) -> f32 {
+    let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (x, y, z) = (fx as i32, fy as i32, fz as i32);
+    let (tx, ty, tz) = (fade(p.x - fx), fade(p.y - fy), fade(p.z - fz));
+    let corner = |dx, dy, dz| hash(x + dx, y + dy, z + dz);
+    lerp(
+        lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), tx),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), tx),
+            ty,
+        ),
+        lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), tx),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), tx),
+            ty,
+        ),
+        tz,
+    )
+}
+
+// Octaves of value noise at doubling frequency and halving amplitude
+pub fn fbm(p: Vec3, octaves: u32) -> f32 {
+    let mut sum = 0.;
+    let mut amplitude = 0.5;
+    let mut p = p;
+    for _ in 0..octaves {
+        sum += value(p) * amplitude;
+        amplitude *= 0.5;
+        p *= 2.;
+    }
+    sum
+}
+
+// Gradient of fbm, by central differences
+pub fn fbm_gradient(p: Vec3, octaves: u32) -> Vec3 {
+    let delta = 0.01;
+    let d = |axis: Vec3| {
+        (fbm(p + axis * delta, octaves) - fbm(p - axis * delta, octaves)) / (2. * delta)
+    };
+    Vec3::new(d(Vec3::unit_x()), d(Vec3::unit_y()), d(Vec3::unit_z()))
+}
@@ -0,0 +1,298 @@
+use std::fmt;
+use std::str::FromStr;
+
+use ultraviolet::Vec3;
+
+use crate::render::to_rgba;
+
+// Range and resolution of the log2 luminance histogram for auto exposure
+const HISTOGRAM_MIN: f32 = -12.;
+const HISTOGRAM_MAX: f32 = 12.;
+const HISTOGRAM_BINS: usize = 256;
+
+// Linear colors of a rendered image and the coverage of each pixel, before
+// conversion to 8 bits
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub colors: Vec<Vec3>,
+    pub coverage: Vec<f32>,
+}
+
+impl Framebuffer {
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .zip(&self.coverage)
+            .flat_map(|(&rgb, &coverage)| to_rgba(rgb, coverage))
+            .collect()
+    }
+
+    // Unclamped RGBA with the colors premultiplied by coverage, as EXR expects
+    pub fn to_rgba_f32(&self) -> Vec<f32> {
+        self.colors
+            .iter()
+            .zip(&self.coverage)
+            .flat_map(|(&rgb, &coverage)| {
+                let rgb = rgb * coverage;
+                [rgb.x, rgb.y, rgb.z, coverage]
+            })
+            .collect()
+    }
+
+    fn get(&self, x: i64, y: i64) -> Vec3 {
+        let x = x.clamp(0, self.width as i64 - 1);
+        let y = y.clamp(0, self.height as i64 - 1);
+        self.colors[(y * self.width as i64 + x) as usize]
+    }
+
+    // Offset of the pixel from the image center, with the corners at length 1
+    fn offset_from_center(&self, x: u32, y: u32) -> (f32, f32) {
+        let (w, h) = (self.width as f32, self.height as f32);
+        let half_diagonal = (w * w + h * h).sqrt() * 0.5;
+        (
+            (x as f32 + 0.5 - w * 0.5) / half_diagonal,
+            (y as f32 + 0.5 - h * 0.5) / half_diagonal,
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    // Spreads the part of each pixel brighter than the threshold over a
+    // radius given in pixels, for a glow around lights and bright highlights
+    Bloom {
+        threshold: f32,
+        radius: f32,
+        intensity: f32,
+    },
+    // Darkens towards the corners, by the strength at the corners
+    Vignette {
+        strength: f32,
+    },
+    // Shifts red outwards and blue inwards, by a fraction of the distance to
+    // the center
+    ChromaticAberration {
+        strength: f32,
+    },
+    // Scales the image so that the luminance at the given percentile (0 to
+    // 1) of covered pixels becomes white, see auto_exposure
+    AutoExposure {
+        percentile: f32,
+    },
+}
+
+impl Effect {
+    pub fn apply(&self, fb: &mut Framebuffer) {
+        match *self {
+            Effect::Bloom {
+                threshold,
+                radius,
+                intensity,
+            } => bloom(fb, threshold, radius, intensity),
+            Effect::Vignette { strength } => {
+                for y in 0..fb.height {
+                    for x in 0..fb.width {
+                        let (dx, dy) = fb.offset_from_center(x, y);
+                        let falloff = (1. - strength * (dx * dx + dy * dy)).max(0.);
+                        fb.colors[(y * fb.width + x) as usize] *= falloff;
+                    }
+                }
+            }
+            Effect::ChromaticAberration { strength } => {
+                let source = fb.clone();
+                let (cx, cy) = (fb.width as f32 * 0.5, fb.height as f32 * 0.5);
+                let at = |x: u32, y: u32, scale: f32| {
+                    let sx = cx + (x as f32 + 0.5 - cx) * scale;
+                    let sy = cy + (y as f32 + 0.5 - cy) * scale;
+                    source.get(sx.floor() as i64, sy.floor() as i64)
+                };
+                for y in 0..fb.height {
+                    for x in 0..fb.width {
+                        let color = &mut fb.colors[(y * fb.width + x) as usize];
+                        // Sampling closer to the center pushes the channel outwards
+                        color.x = at(x, y, 1. - strength).x;
+                        color.z = at(x, y, 1. + strength).z;
+                    }
+                }
+            }
+            Effect::AutoExposure { percentile } => {
+                let exposure = auto_exposure(fb, percentile);
+                for color in &mut fb.colors {
+                    *color *= exposure;
+                }
+            }
+        }
+    }
+}
+
+// Parses effects like "bloom", "vignette=0.5" or "chromatic=0.01", where
+// the value is the threshold for bloom, the percentile for exposure and the
+// strength for the others
+impl FromStr for Effect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => {
+                let value = value
+                    .parse()
+                    .map_err(|err| format!("invalid value for {}: {}", name, err))?;
+                (name, Some(value))
+            }
+            None => (s, None),
+        };
+        match name {
+            "bloom" => Ok(Effect::Bloom {
+                threshold: value.unwrap_or(1.),
+                radius: 8.,
+                intensity: 0.5,
+            }),
+            "vignette" => Ok(Effect::Vignette {
+                strength: value.unwrap_or(0.5),
+            }),
+            "chromatic" => Ok(Effect::ChromaticAberration {
+                strength: value.unwrap_or(0.005),
+            }),
+            "exposure" => Ok(Effect::AutoExposure {
+                percentile: value.unwrap_or(0.95),
+            }),
+            _ => Err(format!("unknown effect: {}", name)),
+        }
+    }
+}
+
+// Maps unbounded linear colors into the displayable range, applied after
+// any effects
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMap {
+    // Cuts off everything above 1
+    #[default]
+    Clamp,
+    // Compresses by luminance, keeping hues but washing out highlights slowly
+    Reinhard,
+    // Filmic curve fitted to ACES, with more contrast and saturated highlights
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(&self, fb: &mut Framebuffer) {
+        for color in &mut fb.colors {
+            *color = match self {
+                ToneMap::Clamp => *color,
+                ToneMap::Reinhard => *color / (1. + luminance(*color)),
+                ToneMap::Aces => color.map(|x| {
+                    let x = x.max(0.);
+                    x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)
+                }),
+            };
+        }
+    }
+}
+
+impl fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ToneMap::Clamp => "clamp",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::Aces => "aces",
+        })
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!("unknown tone mapping: {}", s)),
+        }
+    }
+}
+
+pub fn apply(effects: &[Effect], fb: &mut Framebuffer) {
+    for effect in effects {
+        effect.apply(fb);
+    }
+}
+
+fn luminance(rgb: Vec3) -> f32 {
+    rgb.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+// Exposure that maps the luminance at the given percentile of the covered
+// pixels to 1, found from a histogram of log luminance so that a few very
+// bright pixels don't darken everything else
+pub fn auto_exposure(fb: &Framebuffer, percentile: f32) -> f32 {
+    let mut histogram = [0usize; HISTOGRAM_BINS];
+    let mut count = 0;
+    let bin_size = (HISTOGRAM_MAX - HISTOGRAM_MIN) / HISTOGRAM_BINS as f32;
+    for (&rgb, &coverage) in fb.colors.iter().zip(&fb.coverage) {
+        if coverage == 0. {
+            continue;
+        }
+        let log = luminance(rgb).max(f32::MIN_POSITIVE).log2();
+        let bin = ((log - HISTOGRAM_MIN) / bin_size) as usize;
+        histogram[bin.min(HISTOGRAM_BINS - 1)] += 1;
+        count += 1;
+    }
+    if count == 0 {
+        return 1.;
+    }
+
+    let target = (percentile.clamp(0., 1.) * count as f32).ceil() as usize;
+    let mut seen = 0;
+    for (bin, &n) in histogram.iter().enumerate() {
+        seen += n;
+        if seen >= target.max(1) {
+            let log = HISTOGRAM_MIN + (bin as f32 + 0.5) * bin_size;
+            return 1. / log.exp2();
+        }
+    }
+    1.
+}
+
+fn bloom(fb: &mut Framebuffer, threshold: f32, radius: f32, intensity: f32) {
+    let bright = Framebuffer {
+        colors: fb
+            .colors
+            .iter()
+            .map(|&c| (c - Vec3::broadcast(threshold)).max_by_component(Vec3::zero()))
+            .collect(),
+        ..fb.clone()
+    };
+
+    // Separable gaussian blur, reaching out to three standard deviations
+    let sigma = (radius / 3.).max(0.5);
+    let reach = radius.ceil() as i64;
+    let weights: Vec<f32> = (-reach..=reach)
+        .map(|i| (-(i * i) as f32 / (2. * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+    let blur = |source: &Framebuffer, horizontal: bool| {
+        let mut colors = Vec::with_capacity(source.colors.len());
+        for y in 0..source.height as i64 {
+            for x in 0..source.width as i64 {
+                let mut sum = Vec3::zero();
+                for (i, weight) in (-reach..=reach).zip(&weights) {
+                    let (sx, sy) = if horizontal { (x + i, y) } else { (x, y + i) };
+                    sum += source.get(sx, sy) * *weight;
+                }
+                colors.push(sum / total);
+            }
+        }
+        Framebuffer {
+            colors,
+            ..source.clone()
+        }
+    };
+    let glow = blur(&blur(&bright, true), false);
+
+    for (color, glow) in fb.colors.iter_mut().zip(glow.colors) {
+        *color += glow * intensity;
+    }
+}
@@ -0,0 +1,124 @@
+use std::f32::consts::FRAC_PI_2;
+
+use ultraviolet::Vec3;
+
+use crate::camera::{Lens, Stereo};
+use crate::render::primary_ray;
+use crate::{precision, Scene, Settings};
+
+// Distance camera rays can skip before they reach anything, shared by the
+// pixels of each square tile of the image. Found ahead of rendering by
+// marching a cone around all the rays of a tile, which covers any jitter
+// within their pixels, and starting all of them where it comes close to a
+// surface. Only used with world bounds, as rays without them give up at a
+// distance from where they start, and would see further. Only applies to
+// renders of the same size and camera.
+pub struct TilePrepass {
+    tile: u32,
+    size: (u32, u32),
+    lens: Lens,
+    stereo: Option<Stereo>,
+    columns: u32,
+    starts: Vec<f32>,
+}
+
+impl TilePrepass {
+    pub fn build(
+        scene: &Scene,
+        settings: &Settings,
+        (width, height): (u32, u32),
+        tile: u32,
+    ) -> Self {
+        let tile = tile.max(1);
+        let (columns, rows) = (width.div_ceil(tile), height.div_ceil(tile));
+        let starts = (0..columns * rows)
+            .map(|i| {
+                let (x0, y0) = (i % columns * tile, i / columns * tile);
+                let pixels = (y0..(y0 + tile).min(height))
+                    .flat_map(|y| (x0..(x0 + tile).min(width)).map(move |x| (x, y)));
+                tile_start(scene, settings, (width, height), pixels)
+            })
+            .collect();
+        Self {
+            tile,
+            size: (width, height),
+            lens: settings.lens,
+            stereo: settings.stereo,
+            columns,
+            starts,
+        }
+    }
+
+    // Mean distance rays start at, over all tiles
+    pub fn mean_start(&self) -> f32 {
+        self.starts.iter().sum::<f32>() / self.starts.len().max(1) as f32
+    }
+
+    // Distance along the pixel's rays where marching can start, or 0 when
+    // rendering with another size or camera than the prepass was built for
+    pub(crate) fn start(&self, settings: &Settings, size: (u32, u32), (x, y): (u32, u32)) -> f32 {
+        if size != self.size || settings.lens != self.lens || settings.stereo != self.stereo {
+            return 0.;
+        }
+        self.starts[(x / self.tile + y / self.tile * self.columns) as usize]
+    }
+}
+
+// Start shared by the rays through the pixels, from a cone around the rays
+// through their corners. Curved lenses are covered by widening it by the
+// angle across a pixel.
+fn tile_start(
+    scene: &Scene,
+    settings: &Settings,
+    size: (u32, u32),
+    pixels: impl Iterator<Item = (u32, u32)>,
+) -> f32 {
+    let corners = [(-0.5, -0.5), (0.5, -0.5), (-0.5, 0.5), (0.5, 0.5)];
+    let rays: Vec<[Option<(Vec3, Vec3)>; 4]> = pixels
+        .map(|pixel| corners.map(|jitter| primary_ray(settings, size, pixel, jitter)))
+        .collect();
+    let all = || rays.iter().flatten().flatten();
+    let (apex, _) = match (all().next(), scene.bounds) {
+        (Some(&ray), Some(_)) => ray,
+        _ => return 0.,
+    };
+    let axis = all().fold(Vec3::zero(), |sum, (_, dir)| sum + *dir);
+    if axis.mag_sq() == 0. {
+        return 0.;
+    }
+    let axis = axis.normalized();
+    let angle_between = |a: Vec3, b: Vec3| a.dot(b).clamp(-1., 1.).acos();
+    let pixel_angle = rays
+        .iter()
+        .filter_map(|[a, _, _, b]| Some(angle_between((*a)?.1, (*b)?.1)))
+        .fold(0., f32::max);
+    let angle = all()
+        .map(|&(_, dir)| angle_between(dir, axis))
+        .fold(0., f32::max)
+        + pixel_angle;
+    if angle >= FRAC_PI_2 {
+        return 0.;
+    }
+    // Stereo rays start from more than one eye
+    let spread = all().map(|&(eye, _)| (eye - apex).mag()).fold(0., f32::max);
+    clear_distance(scene, apex, axis, angle.tan(), spread)
+}
+
+// How far the cone from the apex around the axis, with the tangent of its
+// half angle, is clear of surfaces, along with everything within the spread
+// of it. Each step stays within the distance from the previous point on the
+// axis, less the radius of the cone there.
+fn clear_distance(scene: &Scene, apex: Vec3, axis: Vec3, tan: f32, spread: f32) -> f32 {
+    let limit = scene.max_distance();
+    let mut t = 0.;
+    while t < limit {
+        let distance = scene.sample(apex + axis * t).distance;
+        let step = (distance - t * tan - spread) / (1. + tan);
+        if step < precision(scene, t) {
+            break;
+        }
+        t += step;
+    }
+    // Backed off, so that rays start outside of any surface it touches
+    (t.min(limit) - precision(scene, t)).max(0.)
+}
@@ -0,0 +1,66 @@
+use std::cell::Cell;
+
+use ultraviolet::Vec3;
+
+use crate::distfield::Surface;
+use crate::{guess_normal, raycast, raycast_out, RayKind, Scene};
+
+// Ray queries against a scene, for tools that need to know what rays meet
+// without rendering them. Rays see what camera rays do, and give up where
+// they leave the scene's bounds.
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub p: Vec3,
+    pub n: Vec3,
+    // Distance along the ray from where it started
+    pub distance: f32,
+    pub surface: Surface,
+}
+
+// First surface the ray meets
+pub fn cast(scene: &Scene, from: Vec3, dir: Vec3) -> Option<RayHit> {
+    march(scene, from, dir).0
+}
+
+// Number of steps the ray takes until it meets a surface or gives up
+pub fn steps(scene: &Scene, from: Vec3, dir: Vec3) -> usize {
+    march(scene, from, dir).1
+}
+
+// First point along the ray outside of the object it starts in, which is
+// where it starts if that is outside
+pub fn leave(scene: &Scene, from: Vec3, dir: Vec3) -> Option<Vec3> {
+    raycast_out(scene, from, dir, 0.)
+}
+
+// Normal of the surface at p, estimated over the precision at the distance
+// from the eye
+pub fn normal(scene: &Scene, p: Vec3, distance: f32) -> Vec3 {
+    guess_normal(scene, p, precision(scene, distance))
+}
+
+// Smallest step rays take after traveling the distance, and the furthest
+// that surfaces they meet may be behind the point where they are found
+pub fn precision(scene: &Scene, distance: f32) -> f32 {
+    crate::precision(scene, distance)
+}
+
+fn march(scene: &Scene, from: Vec3, dir: Vec3) -> (Option<RayHit>, usize) {
+    let extent = scene.ray_extent(from, dir);
+    let steps = Cell::new(0);
+    let hit = raycast(scene, from, dir, RayKind::Camera, 0., |p| {
+        steps.set(steps.get() + 1);
+        (from - p).mag_sq() < extent * extent
+    })
+    .map(|(s, p)| {
+        let distance = (p - from).mag();
+        RayHit {
+            p,
+            n: normal(scene, p, distance),
+            distance,
+            surface: s.surface,
+        }
+    });
+    (hit, steps.get())
+}
@@ -0,0 +1,200 @@
+use ultraviolet::Vec3;
+
+use crate::camera::EYE;
+use crate::distfield::ObjectId;
+use crate::rng::Rng;
+use crate::{camera_hit, raycast, raytrace, raytrace_layers, RayKind, Scene, Settings};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// Origin and direction of the ray through the pixel, offset by the jitter in
+// pixels, or None if the lens doesn't cover it
+pub(crate) fn primary_ray(
+    settings: &Settings,
+    (width, height): (u32, u32),
+    (x, y): (u32, u32),
+    jitter: (f32, f32),
+) -> Option<(Vec3, Vec3)> {
+    let (size, pixel, side) = match settings.stereo {
+        Some(stereo) => {
+            let (pixel, side) = stereo.view_pixel((width, height), (x, y));
+            (stereo.view_size((width, height)), pixel, side)
+        }
+        None => ((width, height), (x, y), 0.),
+    };
+    let dir = settings.lens.direction(size, pixel, jitter)?;
+    let eye = match settings.stereo {
+        Some(stereo) => stereo.eye(settings.lens, dir, side),
+        None => EYE,
+    };
+    Some((eye, dir))
+}
+
+// Outlines are drawn where the distance to what neighbouring rays see
+// changes by more than this fraction, or the cosine between their normals
+// drops below OUTLINE_NORMAL
+const OUTLINE_DEPTH: f32 = 0.05;
+const OUTLINE_NORMAL: f32 = 0.7;
+
+// Whether the ray through the jittered pixel position lands on an ink
+// outline, comparing what it sees with rays offset by the outline width
+fn on_outline(
+    scene: &Scene,
+    settings: &Settings,
+    size: (u32, u32),
+    pixel: (u32, u32),
+    (jx, jy): (f32, f32),
+    width: f32,
+) -> bool {
+    let probe = |dx: f32, dy: f32| {
+        let (eye, dir) = primary_ray(settings, size, pixel, (jx + dx, jy + dy))?;
+        camera_hit(scene, eye, dir).map(|(p, n)| ((p - eye).mag(), n))
+    };
+    let center = probe(0., 0.);
+    let offset = width * 0.5;
+    [(offset, 0.), (-offset, 0.), (0., offset), (0., -offset)]
+        .into_iter()
+        .any(|(dx, dy)| match (center, probe(dx, dy)) {
+            (Some((da, na)), Some((db, nb))) => {
+                (da - db).abs() > OUTLINE_DEPTH * da.min(db) || na.dot(nb) < OUTLINE_NORMAL
+            }
+            (None, None) => false,
+            _ => true,
+        })
+}
+
+// Averages the samples for a pixel, returning the color and the fraction of
+// samples that hit anything, with hits on shadow catchers counting by their
+// alpha. Light layers are averaged the same way.
+fn sample_pixel(
+    scene: &Scene,
+    settings: &Settings,
+    (width, height): (u32, u32),
+    (x, y): (u32, u32),
+    mut layers: Option<&mut [Vec3]>,
+) -> (Vec3, f32) {
+    let mut rng = Rng::for_pixel(settings.seed, x, y);
+    let start = scene.prepass.as_ref().map_or(0., |prepass| {
+        prepass.start(settings, (width, height), (x, y))
+    });
+
+    let mut rgb = Vec3::zero();
+    let mut alpha = 0.;
+    for i in 0..settings.samples {
+        // The first sample goes through the pixel center, the others are jittered
+        let (jx, jy) = if i == 0 {
+            (0., 0.)
+        } else {
+            (rng.next_f32() - 0.5, rng.next_f32() - 0.5)
+        };
+        let (eye, ray_dir) = match primary_ray(settings, (width, height), (x, y), (jx, jy)) {
+            Some(ray) => ray,
+            None => continue,
+        };
+        let outline = settings.toon.and_then(|toon| toon.outline);
+        if let Some(outline) = outline {
+            if on_outline(scene, settings, (width, height), (x, y), (jx, jy), outline) {
+                // Black ink
+                alpha += 1.;
+                continue;
+            }
+        }
+
+        let color = match layers.as_deref_mut() {
+            Some(layers) => raytrace_layers(scene, settings, &mut rng, eye, ray_dir, start, layers),
+            None => raytrace(scene, settings, &mut rng, eye, ray_dir, start),
+        };
+        if let Some((color, a)) = color {
+            rgb += color * a;
+            alpha += a;
+        }
+    }
+    if alpha == 0. {
+        return (rgb, 0.);
+    }
+
+    for layer in layers.into_iter().flatten() {
+        *layer /= alpha;
+    }
+    (rgb / alpha, alpha / settings.samples as f32)
+}
+
+// The object seen through the center of the pixel, if it has an id
+pub fn pick(
+    settings: &Settings,
+    scene: &Scene,
+    (width, height): (u32, u32),
+    x: u32,
+    y: u32,
+) -> Option<ObjectId> {
+    let (eye, dir) = primary_ray(settings, (width, height), (x, y), (0., 0.))?;
+    let extent = scene.ray_extent(eye, dir);
+    let (s, _) = raycast(scene, eye, dir, RayKind::Camera, 0., |p| {
+        (eye - p).mag_sq() < extent * extent
+    })?;
+    s.surface.object
+}
+
+pub fn render_pixel(
+    scene: &Scene,
+    settings: &Settings,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+) -> [u8; 4] {
+    let (rgb, coverage) = sample_pixel(scene, settings, (width, height), (x, y), None);
+    to_rgba(rgb, coverage)
+}
+
+// Unclamped linear color of the pixel and the fraction of it covered, for
+// processing before conversion with to_rgba
+pub fn render_pixel_hdr(
+    scene: &Scene,
+    settings: &Settings,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+) -> (Vec3, f32) {
+    sample_pixel(scene, settings, (width, height), (x, y), None)
+}
+
+pub(crate) fn to_rgba(rgb: Vec3, coverage: f32) -> [u8; 4] {
+    if coverage == 0. {
+        return [0, 0, 0, 0];
+    }
+
+    let rgb_scaled = rgb * 255.;
+    [
+        rgb_scaled.x as _,
+        rgb_scaled.y as _,
+        rgb_scaled.z as _,
+        (coverage * 255.) as _,
+    ]
+}
+
+// Renders the pixel along with the contribution of each light to it,
+// followed by everything else. The layers add up to the unclamped color.
+pub fn render_pixel_layers(
+    scene: &Scene,
+    settings: &Settings,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+) -> ([u8; 4], Vec<Vec3>) {
+    let mut layers = vec![Vec3::zero(); scene.lights.len() + 1];
+    let (rgb, coverage) = sample_pixel(scene, settings, (width, height), (x, y), Some(&mut layers));
+    (to_rgba(rgb, coverage), layers)
+}
+
+pub fn render_rgba(scene: &Scene, settings: &Settings, width: u32, height: u32) -> Vec<u8> {
+    let pixel = |i: u32| render_pixel(scene, settings, width, height, i % width, i / width);
+    #[cfg(feature = "parallel")]
+    let pixels = (0..width * height).into_par_iter().flat_map_iter(pixel);
+    #[cfg(not(feature = "parallel"))]
+    let pixels = (0..width * height).flat_map(pixel);
+    pixels.collect()
+}
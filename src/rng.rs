@@ -0,0 +1,37 @@
+// PCG32, small and fast enough to seed per pixel so that renders are
+// reproducible regardless of how pixels are scheduled across threads
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self {
+            state: seed.wrapping_add(0x853c49e6748fea9b),
+        };
+        rng.next_u32();
+        rng
+    }
+
+    pub fn for_pixel(seed: u64, x: u32, y: u32) -> Self {
+        // splitmix64 to spread neighbouring pixels over the state space
+        let mut z = seed ^ ((y as u64) << 32 | x as u64);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        Self::new(z ^ (z >> 31))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use ultraviolet::Vec3;
+
+use crate::bytecode::{Op, Program};
+use crate::caustics::Caustics;
+use crate::distfield::{ObjectId, Sample, Sdf, Surface};
+use crate::json::Json;
+use crate::light::{Light, LightGroups};
+use crate::mipmap::DistanceMipmap;
+use crate::occlusion::OcclusionCache;
+use crate::prepass::TilePrepass;
+use crate::sky::Sky;
+use crate::validate::{check_color, check_normalized, check_surface, Warning};
+use crate::Hit;
+
+// Cuts away everything on the side of the plane that the normal points to,
+// optionally showing the cut faces with the cap surface
+#[derive(Clone, Copy, Debug)]
+pub struct ClipPlane {
+    pub normal: Vec3,
+    pub offset: f32,
+    pub cap: Option<Surface>,
+}
+
+impl ClipPlane {
+    // The plane through the points p where p.dot(normal) == offset
+    pub fn new(normal: Vec3, offset: f32) -> Self {
+        Self {
+            normal: normal.normalized(),
+            offset,
+            cap: None,
+        }
+    }
+
+    pub fn with_cap(self, cap: Surface) -> Self {
+        Self {
+            cap: Some(cap),
+            ..self
+        }
+    }
+
+    fn to_json(self) -> Json {
+        Json::object([
+            ("normal", self.normal.into()),
+            ("offset", self.offset.into()),
+            ("cap", self.cap.map(|cap| cap.to_json()).into()),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Self {
+            normal: json.required("normal", Json::direction)?,
+            offset: json.required("offset", Json::f32)?,
+            cap: match json.optional("cap", Some)? {
+                Some(cap) => Some(Surface::from_json(cap)?),
+                None => None,
+            },
+        })
+    }
+}
+
+// Without bounds, rays give up this many units of the scene's scale from
+// where they start
+const MAX_DISTANCE: f32 = 1000.;
+
+// The fingerprint samples this many points along each axis, spread over
+// the cube reaching this far from the origin
+const FINGERPRINT_STEPS: u32 = 16;
+const FINGERPRINT_EXTENT: f32 = 200.;
+
+// Version of the scene file format, see Scene::save
+const FILE_VERSION: u32 = 1;
+
+// FNV-1a, over everything written to it
+struct Fnv(u64);
+
+impl fmt::Write for Fnv {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+        Ok(())
+    }
+}
+
+// Custom shading for an object, see Scene::set_shader
+pub type Shader = dyn Fn(&Hit) -> Vec3 + Send + Sync;
+
+pub struct Scene {
+    sdf: Box<dyn Sdf>,
+    pub lights: Vec<Light>,
+    pub light_groups: LightGroups,
+    // Seen by rays that miss everything, instead of leaving pixels empty
+    pub sky: Option<Sky>,
+    pub clip_planes: Vec<ClipPlane>,
+    // Shadow tint for lights that don't have their own, see
+    // Light::with_shadow_tint
+    pub shadow_tint: Option<Vec3>,
+    // Length of a unit relative to the scenes the defaults are tuned for,
+    // where objects are around 1 in size. Scales the precision rays march
+    // with and the distance they give up at.
+    pub unit_scale: f32,
+    // Min and max corners of a box around everything in the scene. Rays give
+    // up where they leave it, instead of after a fixed distance.
+    pub bounds: Option<(Vec3, Vec3)>,
+    // Light reflected onto surfaces by mirrors, traced ahead of rendering,
+    // see Caustics::trace
+    pub caustics: Option<Caustics>,
+    // Skips shadow rays through empty space, see OcclusionCache. Must be
+    // rebuilt when the scene changes.
+    pub occlusion: Option<OcclusionCache>,
+    // Lets rays skip through empty space, see DistanceMipmap. Must also be
+    // rebuilt when the scene changes.
+    pub empty_space: Option<DistanceMipmap>,
+    // Where camera rays start marching, see TilePrepass
+    pub prepass: Option<TilePrepass>,
+    shaders: HashMap<ObjectId, Box<Shader>>,
+}
+
+impl Scene {
+    pub fn new(sdf: impl Sdf + 'static, lights: Vec<Light>) -> Self {
+        Self {
+            sdf: Box::new(sdf),
+            lights,
+            light_groups: LightGroups::default(),
+            sky: None,
+            clip_planes: Vec::new(),
+            shadow_tint: None,
+            unit_scale: 1.,
+            bounds: None,
+            caustics: None,
+            occlusion: None,
+            empty_space: None,
+            prepass: None,
+            shaders: HashMap::new(),
+        }
+    }
+
+    pub fn sample(&self, p: Vec3) -> Sample {
+        let mut s = self.sdf.sample(p);
+        for plane in &self.clip_planes {
+            // Intersected with the half space behind the plane
+            let distance = p.dot(plane.normal) - plane.offset;
+            if distance > s.distance {
+                s = Sample {
+                    distance,
+                    surface: plane.cap.unwrap_or(s.surface),
+                };
+            }
+        }
+        s
+    }
+
+    // Replaces the lighting of the object's surfaces with the shader's color.
+    // Reflections are still blended over it by the surface's reflectivity.
+    pub fn set_shader(
+        &mut self,
+        object: ObjectId,
+        shader: impl Fn(&Hit) -> Vec3 + Send + Sync + 'static,
+    ) {
+        self.shaders.insert(object, Box::new(shader));
+    }
+
+    pub(crate) fn shader(&self, surface: &Surface) -> Option<&Shader> {
+        self.shaders.get(&surface.object?).map(|shader| &**shader)
+    }
+
+    // Mean curvature of the surface through p, from the second differences
+    // of the field over the radius. Positive on convex edges and bumps,
+    // negative in cavities and creases, and 1 / r for a sphere of radius r.
+    pub fn curvature(&self, p: Vec3, radius: f32) -> f32 {
+        let center = self.sample(p).distance;
+        let mut sum = 0.;
+        for axis in [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()] {
+            let d = axis * radius;
+            sum += self.sample(p + d).distance + self.sample(p - d).distance - 2. * center;
+        }
+        sum / (2. * radius * radius)
+    }
+
+    // Checks for common authoring problems that would show up as artifacts
+    // in the render. Fields given as plain functions can't be checked.
+    pub fn validate(&self) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        self.sdf.validate(&mut warnings);
+        for light in &self.lights {
+            light.validate(&mut warnings);
+        }
+        if let Some(tint) = self.shadow_tint {
+            check_color(&mut warnings, "shadow tint", tint);
+        }
+        if self.unit_scale <= 0. {
+            warnings.push(Warning::OutOfRange {
+                what: "unit scale",
+                value: self.unit_scale,
+            });
+        }
+        if let Some((min, max)) = self.bounds {
+            let size = (max - min).component_min();
+            if size <= 0. {
+                warnings.push(Warning::EmptyPrimitive {
+                    what: "world bounds",
+                    size,
+                });
+            }
+        }
+        for plane in &self.clip_planes {
+            check_normalized(&mut warnings, "clip plane normal", plane.normal.mag());
+            if let Some(cap) = &plane.cap {
+                check_surface(&mut warnings, cap);
+            }
+        }
+        warnings
+    }
+
+    // Farthest a ray can get from where it starts, such as across the bounds
+    pub(crate) fn max_distance(&self) -> f32 {
+        match self.bounds {
+            Some((min, max)) => (max - min).mag(),
+            None => MAX_DISTANCE * self.unit_scale,
+        }
+    }
+
+    // Distance along the ray to where it leaves the bounds, or max_distance
+    // without bounds. Zero for rays that miss them.
+    pub(crate) fn ray_extent(&self, from: Vec3, dir: Vec3) -> f32 {
+        let (min, max) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return self.max_distance(),
+        };
+        // Slab test, with the entry and exit along each axis
+        let (mut near, mut far) = (0f32, f32::INFINITY);
+        for axis in 0..3 {
+            let t0 = (min[axis] - from[axis]) / dir[axis];
+            let t1 = (max[axis] - from[axis]) / dir[axis];
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        if near <= far {
+            far
+        } else {
+            0.
+        }
+    }
+
+    // Hash of the field sampled on a grid, along with the lights, sky, clip
+    // planes and the other settings above, and the number of caustics
+    // photons. Stable between runs and builds, so it identifies the
+    // scene a render was made from, short of changes between grid points.
+    // Shaders can't be hashed, so only the objects they are set for count.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = Fnv(0xcbf29ce484222325);
+        let steps = FINGERPRINT_STEPS as f32 - 1.;
+        for i in 0..FINGERPRINT_STEPS.pow(3) {
+            let cell = Vec3::new(
+                (i % FINGERPRINT_STEPS) as f32,
+                (i / FINGERPRINT_STEPS % FINGERPRINT_STEPS) as f32,
+                (i / FINGERPRINT_STEPS / FINGERPRINT_STEPS) as f32,
+            );
+            let p = (cell / steps * 2. - Vec3::one()) * FINGERPRINT_EXTENT;
+            let s = self.sample(p);
+            let _ = write!(hash, "{:?}{:?}", s.distance, s.surface);
+        }
+        let _ = write!(
+            hash,
+            "{:?}{:?}{:?}{:?}{:?}{:?}",
+            self.lights, self.sky, self.clip_planes, self.shadow_tint, self.unit_scale, self.bounds
+        );
+        let mut shaded: Vec<_> = self.shaders.keys().map(|id| id.0).collect();
+        shaded.sort_unstable();
+        let _ = write!(hash, "{:?}", shaded);
+        let _ = write!(hash, "{:?}", self.caustics.as_ref().map(Caustics::len));
+        hash.0
+    }
+
+    // Replaces a scene graph with its flattened program, if all nodes support it
+    pub fn compile(&mut self) -> bool {
+        match Program::compile(&*self.sdf) {
+            Some(program) => {
+                self.sdf = Box::new(program);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Writes the scene to a JSON file that Scene::load reads back into one
+    // that renders the same, with the field saved as its compiled program.
+    // Fails for fields that don't compile, such as text and scripts, and for
+    // scenes with shaders. Caustics and the other caches built ahead of
+    // rendering aren't saved, and have to be built again after loading.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        if !self.shaders.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "scenes with shaders can't be saved",
+            ));
+        }
+        let program = Program::compile(&*self.sdf).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::Unsupported,
+                "the scene has a field that doesn't compile",
+            )
+        })?;
+        let json = Json::object([
+            ("version", FILE_VERSION.into()),
+            (
+                "ops",
+                Json::Array(program.ops().iter().map(|op| op.to_json()).collect()),
+            ),
+            (
+                "lights",
+                Json::Array(self.lights.iter().map(|light| light.to_json()).collect()),
+            ),
+            ("light_groups", self.light_groups.to_json()),
+            ("sky", self.sky.map(|sky| sky.to_json()).into()),
+            (
+                "clip_planes",
+                Json::Array(self.clip_planes.iter().map(|p| p.to_json()).collect()),
+            ),
+            ("shadow_tint", self.shadow_tint.into()),
+            ("unit_scale", self.unit_scale.into()),
+            (
+                "bounds",
+                self.bounds
+                    .map(|(min, max)| Json::Array(vec![min.into(), max.into()]))
+                    .into(),
+            ),
+        ]);
+        let text = json
+            .to_text()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        fs::write(path, text)
+    }
+
+    // Reads a scene written by Scene::save
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    // Reads a scene from the text of a scene file
+    pub fn from_json(text: &str) -> io::Result<Self> {
+        Self::parse(text).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        let json = Json::parse(text)?;
+        let version = json.required("version", Json::u32)?;
+        if version != FILE_VERSION {
+            return Err(format!("unsupported version {}", version));
+        }
+        let ops = json
+            .required("ops", Json::array)?
+            .iter()
+            .map(Op::from_json)
+            .collect::<Result<_, _>>()?;
+        let program = Program::from_ops(ops).ok_or("ops don't form a valid program")?;
+        let list = |key| {
+            json.optional(key, Json::array)
+                .map(Option::unwrap_or_default)
+        };
+        let mut scene = Scene::new(
+            program,
+            list("lights")?
+                .iter()
+                .map(Light::from_json)
+                .collect::<Result<_, _>>()?,
+        );
+        if let Some(groups) = json.optional("light_groups", Some)? {
+            scene.light_groups = LightGroups::from_json(groups)?;
+        }
+        if let Some(sky) = json.optional("sky", Some)? {
+            scene.sky = Some(Sky::from_json(sky)?);
+        }
+        scene.clip_planes = list("clip_planes")?
+            .iter()
+            .map(ClipPlane::from_json)
+            .collect::<Result<_, _>>()?;
+        scene.shadow_tint = json.optional("shadow_tint", Json::vec3)?;
+        if let Some(scale) = json.optional("unit_scale", Json::f32)? {
+            if scale <= 0. {
+                return Err("unit_scale must be positive".into());
+            }
+            scene.unit_scale = scale;
+        }
+        scene.bounds = json.optional("bounds", |bounds| match bounds.array()? {
+            [min, max] => Some((min.vec3()?, max.vec3()?)),
+            _ => None,
+        })?;
+        if let Some((min, max)) = scene.bounds {
+            if min.x > max.x || min.y > max.y || min.z > max.z {
+                return Err("bounds have a min greater than their max".into());
+            }
+        }
+        Ok(scene)
+    }
+}
@@ -0,0 +1,652 @@
+use ultraviolet::{Rotor3, Vec3};
+
+use crate::distfield::{
+    displace, distfield, intersect, invert, mandelbulb, sphere, union, Sample, Sdf, Surface,
+    Visibility,
+};
+use crate::generate::{self, Turtle};
+use crate::graph::{Displace, Intersect, Invert, Sphere, Text, Transform, Union, Warp};
+use crate::noise;
+use crate::rng::Rng;
+use crate::sky::Sky;
+use crate::{Light, LightGroups, Scene};
+
+pub fn default_lights() -> Vec<Light> {
+    vec![
+        Light::new(Vec3::new(500., 1000., -300.), Vec3::new(1.0, 0.5, 0.)),
+        Light::new(Vec3::new(-700., -500., -10.), Vec3::new(0., 0.5, 1.0)),
+        Light::new(Vec3::new(-700., 1500., 10.), Vec3::new(0.5, 0., 1.0)),
+        Light::new(Vec3::new(10., -20., -50.), Vec3::new(0.3, 0.2, 0.2)),
+    ]
+}
+
+pub fn default() -> Scene {
+    Scene::new(distfield, default_lights())
+}
+
+// The default scene assembled from graph nodes instead of a function
+pub fn graph() -> Scene {
+    let mat1 = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.4);
+    let mat2 = Surface::new(Vec3::new(0.4, 0.8, 1.0), 0.2);
+    let mat3 = Surface::new(Vec3::new(1.0, 0.4, 0.8), 0.0);
+    let sdf = Intersect(
+        Box::new(Union(
+            Box::new(Warp(Box::new(Sphere {
+                center: Vec3::new(-30., 0., 0.),
+                radius: 65.,
+                surface: mat1,
+            }))),
+            Box::new(Sphere {
+                center: Vec3::new(30., 10., -10.),
+                radius: 50.,
+                surface: mat2,
+            }),
+        )),
+        Box::new(Invert(Box::new(Displace {
+            scale: 10.,
+            detail: 0.2,
+            child: Box::new(Sphere {
+                center: Vec3::new(10., -20., -60.),
+                radius: 30.,
+                surface: mat3,
+            }),
+        }))),
+    );
+    Scene::new(sdf, default_lights())
+}
+
+fn compiled(mut scene: Scene) -> Scene {
+    scene.compile();
+    scene
+}
+
+// The many primitives scene as a graph, where dispatch overhead dominates
+pub fn many_primitives_graph() -> Scene {
+    let mat1 = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.3);
+    let mat2 = Surface::new(Vec3::new(0.4, 0.8, 1.0), 0.0);
+    let mut sdf: Box<dyn Sdf> = Box::new(Sphere {
+        center: Vec3::new(0., 0., 200.),
+        radius: 150.,
+        surface: mat2,
+    });
+    for i in 0..8 {
+        for j in 0..8 {
+            let sphere = Sphere {
+                center: Vec3::new(i as f32 * 25. - 87.5, j as f32 * 25. - 87.5, 0.),
+                radius: 10.,
+                surface: if (i + j) % 2 == 0 { mat1 } else { mat2 },
+            };
+            sdf = Box::new(Union(sdf, Box::new(sphere)));
+        }
+    }
+    Scene::new(sdf, default_lights())
+}
+
+// A branching tree from an L-system among randomly packed boulders and a
+// jittered grid of pebbles, all generated from a fixed seed
+pub fn generated() -> Scene {
+    let mut rng = Rng::new(1);
+    let bark = Surface::new(Vec3::new(0.5, 0.35, 0.2), 0.0);
+    let stones = [
+        Surface::new(Vec3::new(0.6, 0.6, 0.65), 0.1),
+        Surface::new(Vec3::new(0.7, 0.55, 0.4), 0.0),
+        Surface::new(Vec3::new(0.3, 0.5, 0.7), 0.4),
+    ];
+    let ground = Surface::new(Vec3::new(0.5, 0.6, 0.4), 0.0);
+
+    let branches = generate::l_system("F", &[('F', "F[+F][-F][&F][^F]")], 3);
+    let tree = Turtle {
+        length: 30.,
+        radius: 4.,
+        angle: 0.5,
+        jitter: 0.3,
+        taper: 0.7,
+        surface: bark,
+    };
+    let mut nodes = generate::turtle(&mut rng, &branches, Vec3::new(0., -50., 20.), tree);
+    nodes.extend(generate::sphere_pack(
+        &mut rng,
+        (Vec3::new(-140., -55., -40.), Vec3::new(140., -25., 80.)),
+        12,
+        (6., 14.),
+        &stones,
+    ));
+    nodes.extend(generate::jittered_grid(
+        &mut rng,
+        Vec3::new(-90., -50., -60.),
+        Vec3::new(30., 1., 20.),
+        [7, 1, 3],
+        0.3,
+        |rng, center| {
+            Box::new(Sphere {
+                center,
+                radius: 2. + 2. * rng.next_f32(),
+                surface: stones[0],
+            })
+        },
+    ));
+    nodes.push(Box::new(Sphere {
+        center: Vec3::new(0., -10050., 0.),
+        radius: 10000.,
+        surface: ground,
+    }));
+    Scene::new(generate::union_all(nodes).unwrap(), default_lights())
+}
+
+pub fn simple() -> Scene {
+    let mat = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.0);
+    Scene::new(
+        move |p| sphere(p, Vec3::new(0., 0., 0.), 60., mat),
+        default_lights(),
+    )
+}
+
+pub fn many_primitives() -> Scene {
+    let mat1 = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.3);
+    let mat2 = Surface::new(Vec3::new(0.4, 0.8, 1.0), 0.0);
+    Scene::new(
+        move |p: Vec3| {
+            let mut s = sphere(p, Vec3::new(0., 0., 200.), 150., mat2);
+            for i in 0..8 {
+                for j in 0..8 {
+                    let center = Vec3::new(i as f32 * 25. - 87.5, j as f32 * 25. - 87.5, 0.);
+                    let mat = if (i + j) % 2 == 0 { mat1 } else { mat2 };
+                    s = union(s, sphere(p, center, 10., mat));
+                }
+            }
+            s
+        },
+        default_lights(),
+    )
+}
+
+pub fn fractal() -> Scene {
+    let mat = Surface::new(Vec3::new(0.4, 0.8, 1.0), 0.0);
+    Scene::new(
+        move |p| mandelbulb(p, Vec3::new(0., 0., 0.), 80., 8., mat),
+        default_lights(),
+    )
+}
+
+pub fn displacement() -> Scene {
+    let mat1 = Surface::new(Vec3::new(1.0, 0.4, 0.8), 0.0);
+    let mat2 = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.4);
+    Scene::new(
+        move |p| -> Sample {
+            intersect(
+                displace(p, 8., 0.5, sphere(p, Vec3::new(0., 0., 0.), 70., mat1)),
+                invert(displace(
+                    p,
+                    5.,
+                    1.0,
+                    sphere(p, Vec3::new(20., 20., -60.), 40., mat2),
+                )),
+            )
+        },
+        default_lights(),
+    )
+}
+
+// Glossy spheres under small area lights, with the rim light linked to the
+// spheres only
+pub fn glossy() -> Scene {
+    let mut groups = LightGroups::default();
+    let rim = groups.mask("rim").unwrap();
+    let mat1 = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.5)
+        .with_roughness(0.2)
+        .with_object(1);
+    let mat2 = Surface::new(Vec3::new(0.4, 0.8, 1.0), 0.3)
+        .with_roughness(0.05)
+        .with_object(2);
+    let floor = Surface::new(Vec3::new(0.8, 0.8, 0.8), 0.0)
+        .with_light_mask(!rim)
+        .with_object(3);
+    let mut scene = Scene::new(
+        move |p| {
+            union(
+                union(
+                    sphere(p, Vec3::new(-45., -10., 0.), 40., mat1),
+                    sphere(p, Vec3::new(45., -10., 0.), 40., mat2),
+                ),
+                sphere(p, Vec3::new(0., -10050., 0.), 10000., floor),
+            )
+        },
+        vec![
+            Light::area(Vec3::new(-200., 300., -200.), 40., Vec3::new(1.0, 0.9, 0.7)),
+            Light::area(Vec3::new(300., 100., -100.), 10., Vec3::new(0.3, 0.4, 0.6))
+                .with_groups(rim),
+        ],
+    );
+    scene.light_groups = groups;
+    scene
+}
+
+// Spheres on the ground under an analytic daylight sky
+pub fn outdoor() -> Scene {
+    let mat1 = Surface::new(Vec3::new(0.9, 0.9, 0.9), 0.6).with_object(1);
+    let mat2 = Surface::new(Vec3::new(1.0, 0.4, 0.3), 0.1).with_object(2);
+    let ground = Surface::new(Vec3::new(0.5, 0.45, 0.35), 0.0).with_object(3);
+    let sky = Sky::preetham(Vec3::new(-0.5, 0.5, -0.6), 3.);
+    let mut scene = Scene::new(
+        move |p| {
+            union(
+                union(
+                    sphere(p, Vec3::new(-40., -20., 20.), 40., mat1),
+                    sphere(p, Vec3::new(50., -35., -10.), 25., mat2),
+                ),
+                sphere(p, Vec3::new(0., -10060., 0.), 10000., ground),
+            )
+        },
+        sky.sun_light(1.0).into_iter().collect(),
+    );
+    scene.sky = Some(sky);
+    scene
+}
+
+// Clear blue sky without a sun, to put behind any scene
+pub fn gradient_sky() -> Sky {
+    Sky::gradient(
+        Vec3::new(0.2, 0.4, 0.8),
+        Vec3::new(0.8, 0.85, 0.9),
+        Vec3::new(0.25, 0.22, 0.2),
+    )
+}
+
+// Mirror and chrome spheres reflecting a gradient sky with the sun in it
+pub fn mirrors() -> Scene {
+    let mirror = Surface::new(Vec3::new(0.9, 0.9, 0.9), 0.9);
+    let chrome = Surface::new(Vec3::new(0.8, 0.6, 0.3), 0.7).with_roughness(0.1);
+    let ground = Surface::new(Vec3::new(0.4, 0.4, 0.4), 0.2);
+    let sky = gradient_sky().with_sun(Vec3::new(-0.4, 0.5, -0.7), 0.05, Vec3::new(4., 3.6, 3.));
+    let mut scene = Scene::new(
+        move |p| {
+            union(
+                union(
+                    sphere(p, Vec3::new(-40., -20., 20.), 40., mirror),
+                    sphere(p, Vec3::new(50., -35., -10.), 25., chrome),
+                ),
+                sphere(p, Vec3::new(0., -10060., 0.), 10000., ground),
+            )
+        },
+        sky.sun_light(0.25).into_iter().collect(),
+    );
+    scene.sky = Some(sky);
+    scene
+}
+
+// A mirror sphere with rough rust patches and a dimly glowing ember, with
+// their materials varied by noise over the surface
+pub fn rust() -> Scene {
+    let mirror = Surface::new(Vec3::new(0.9, 0.9, 0.9), 0.9);
+    let rust = Surface::new(Vec3::new(0.45, 0.2, 0.08), 0.05).with_roughness(0.8);
+    let rock = Surface::new(Vec3::new(0.15, 0.12, 0.1), 0.0);
+    let ember = rock.with_emission(Vec3::new(1.0, 0.35, 0.05));
+    let ground = Surface::new(Vec3::new(0.5, 0.5, 0.5), 0.0);
+    let mask = |p: Vec3, scale: f32, coverage: f32| {
+        let n = noise::fbm(p / scale, 4) * 0.5 + 0.5;
+        ((n - 1. + coverage) * 8.).clamp(0., 1.)
+    };
+    Scene::new(
+        move |p| {
+            union(
+                union(
+                    sphere(
+                        p,
+                        Vec3::new(-40., -10., 0.),
+                        40.,
+                        mirror.mix(rust, mask(p, 12., 0.45)),
+                    ),
+                    sphere(
+                        p,
+                        Vec3::new(50., -25., -10.),
+                        25.,
+                        rock.mix(ember, mask(p, 6., 0.35)),
+                    ),
+                ),
+                sphere(p, Vec3::new(0., -10050., 0.), 10000., ground),
+            )
+        },
+        default_lights(),
+    )
+}
+
+// Carved and lumpy stone, with worn pale edges and dark dirt in the pits
+// and creases, from the curvature of the surface
+pub fn worn() -> Scene {
+    let stone = Surface::new(Vec3::new(0.5, 0.45, 0.4), 0.0).with_wear(
+        6.,
+        Vec3::new(0.95, 0.9, 0.8),
+        Vec3::new(0.1, 0.08, 0.05),
+    );
+    let floor = Surface::new(Vec3::new(0.6, 0.6, 0.6), 0.0);
+    Scene::new(
+        move |p| {
+            let mut carved = sphere(p, Vec3::new(-45., -10., 0.), 40., stone);
+            for center in [
+                Vec3::new(-45., 20., -35.),
+                Vec3::new(-75., -5., -25.),
+                Vec3::new(-20., -20., -40.),
+            ] {
+                carved = intersect(carved, invert(sphere(p, center, 18., stone)));
+            }
+            let lumpy = displace(p, 6., 0.15, sphere(p, Vec3::new(45., -10., 0.), 38., stone));
+            union(
+                union(carved, lumpy),
+                sphere(p, Vec3::new(0., -10050., 0.), 10000., floor),
+            )
+        },
+        default_lights(),
+    )
+}
+
+// A title standing on the ground, turned slightly towards the light
+pub fn title() -> Scene {
+    let letters = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.3).with_object(1);
+    let ground = Surface::new(Vec3::new(0.6, 0.6, 0.6), 0.0).with_object(2);
+    let text = Transform {
+        offset: Vec3::new(0., -13., 0.),
+        rotation: Rotor3::from_rotation_xz(0.25),
+        scale: 1.,
+        child: Box::new(Text::new("Raycast", 24., 10., letters)),
+    };
+    let sdf = Union(
+        Box::new(text),
+        Box::new(Sphere {
+            center: Vec3::new(0., -10025., 0.),
+            radius: 10000.,
+            surface: ground,
+        }),
+    );
+    Scene::new(sdf, default_lights())
+}
+
+// Wax and jade lit mostly from behind, so that light shows through the
+// thinner parts
+pub fn subsurface() -> Scene {
+    let wax =
+        Surface::new(Vec3::new(0.9, 0.8, 0.6), 0.0).with_scatter(Vec3::new(1.0, 0.5, 0.2), 30.);
+    let jade =
+        Surface::new(Vec3::new(0.3, 0.6, 0.4), 0.1).with_scatter(Vec3::new(0.3, 0.9, 0.5), 25.);
+    let floor = Surface::new(Vec3::new(0.6, 0.6, 0.6), 0.0);
+    Scene::new(
+        move |p| {
+            union(
+                union(
+                    sphere(p, Vec3::new(-45., -10., 0.), 40., wax),
+                    sphere(p, Vec3::new(45., -20., 0.), 30., jade),
+                ),
+                sphere(p, Vec3::new(0., -10050., 0.), 10000., floor),
+            )
+        },
+        vec![
+            Light::new(Vec3::new(-100., 150., 500.), Vec3::new(1.0, 0.9, 0.8)),
+            Light::new(Vec3::new(200., 200., -300.), Vec3::new(0.2, 0.2, 0.25)),
+        ],
+    )
+}
+
+// Spheres with fine detail from bump noise rather than displacement
+pub fn bump() -> Scene {
+    let mat1 = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.3).with_bump(4., 0.6);
+    let mat2 = Surface::new(Vec3::new(0.4, 0.8, 1.0), 0.0).with_bump(15., 0.5);
+    Scene::new(
+        move |p| {
+            union(
+                sphere(p, Vec3::new(-45., 0., 0.), 40., mat1),
+                sphere(p, Vec3::new(45., 0., 0.), 40., mat2),
+            )
+        },
+        default_lights(),
+    )
+}
+
+// A mirror sphere next to one that casts no shadow and doesn't show up in
+// reflections, with an unseen blocker shading part of the floor
+pub fn visibility() -> Scene {
+    let mirror = Surface::new(Vec3::new(0.9, 0.9, 0.9), 0.8);
+    let ghost = Surface::new(Vec3::new(1.0, 0.4, 0.3), 0.0).with_visibility(Visibility {
+        shadow: false,
+        reflection: false,
+        ..Visibility::ALL
+    });
+    let blocker = Surface::new(Vec3::zero(), 0.0).with_visibility(Visibility {
+        camera: false,
+        reflection: false,
+        ..Visibility::ALL
+    });
+    let floor = Surface::new(Vec3::new(0.8, 0.8, 0.8), 0.0);
+    Scene::new(
+        move |p| {
+            union(
+                union(
+                    sphere(p, Vec3::new(-45., -10., 0.), 40., mirror),
+                    sphere(p, Vec3::new(45., -20., -20.), 30., ghost),
+                ),
+                union(
+                    sphere(p, Vec3::new(4., 90., -116.), 25., blocker),
+                    sphere(p, Vec3::new(0., -10050., 0.), 10000., floor),
+                ),
+            )
+        },
+        vec![Light::area(
+            Vec3::new(100., 300., -200.),
+            20.,
+            Vec3::new(1.0, 0.9, 0.8),
+        )],
+    )
+}
+
+// Spheres on a shadow catcher, to be composited over a photo of the ground
+pub fn shadow_catcher() -> Scene {
+    let mat1 = Surface::new(Vec3::new(1.0, 0.8, 0.4), 0.3);
+    let mat2 = Surface::new(Vec3::new(0.4, 0.8, 1.0), 0.0);
+    let ground = Surface::shadow_catcher();
+    Scene::new(
+        move |p| {
+            union(
+                union(
+                    sphere(p, Vec3::new(-45., -10., 0.), 40., mat1),
+                    sphere(p, Vec3::new(45., -20., -20.), 30., mat2),
+                ),
+                sphere(p, Vec3::new(0., -10050., 0.), 10000., ground),
+            )
+        },
+        vec![
+            Light::area(Vec3::new(-200., 300., -200.), 40., Vec3::new(1.0, 0.9, 0.7)),
+            Light::new(Vec3::new(300., 100., -100.), Vec3::new(0.3, 0.4, 0.6)),
+        ],
+    )
+}
+
+// The camera inside a large lumpy object, looking at the inside of its
+// surface lit from within
+pub fn interior() -> Scene {
+    let wall = Surface::new(Vec3::new(0.9, 0.8, 0.7), 0.0).with_two_sided(true);
+    Scene::new(
+        move |p| displace(p, 10., 0.04, sphere(p, Vec3::new(0., 0., 0.), 300., wall)),
+        vec![
+            Light::new(Vec3::new(0., 150., 0.), Vec3::new(1.0, 0.8, 0.6)),
+            Light::new(Vec3::new(-100., -100., 100.), Vec3::new(0.2, 0.3, 0.6)),
+        ],
+    )
+}
+
+// A clear glass sphere and a tinted one over a checkered floor, which they
+// bend into view upside down. The tint deepens towards the middle, where the
+// glass is thickest.
+pub fn glass() -> Scene {
+    let clear = Surface::new(Vec3::one(), 0.0).with_refraction(1.5);
+    let tinted = clear.with_absorption(Vec3::new(0.45, 0.85, 0.6), 30.);
+    let light = Surface::new(Vec3::new(0.8, 0.8, 0.8), 0.0);
+    let dark = Surface::new(Vec3::new(0.2, 0.2, 0.25), 0.0);
+    let sky = gradient_sky();
+    let mut scene = Scene::new(
+        move |p: Vec3| {
+            let check = (p.x / 20.).floor() + (p.z / 20.).floor();
+            let floor = if check.rem_euclid(2.) < 1. {
+                light
+            } else {
+                dark
+            };
+            union(
+                union(
+                    sphere(p, Vec3::new(-45., -10., 0.), 40., clear),
+                    sphere(p, Vec3::new(45., -20., -20.), 30., tinted),
+                ),
+                sphere(p, Vec3::new(0., -10050., 0.), 10000., floor),
+            )
+        },
+        vec![Light::area(
+            Vec3::new(-200., 300., -200.),
+            20.,
+            Vec3::new(1.0, 0.95, 0.9),
+        )],
+    );
+    scene.sky = Some(sky);
+    scene
+}
+
+// A glass globe of water with an air bubble and a tinted glass marble in it.
+// Each is carved out of what holds it, with the carved surface made of the
+// outer material, so rays bend by the media on both sides of every surface.
+pub fn nested() -> Scene {
+    let glass = Surface::new(Vec3::one(), 0.0).with_refraction(1.5);
+    let water = Surface::new(Vec3::one(), 0.0)
+        .with_refraction(1.33)
+        .with_absorption(Vec3::new(0.75, 0.9, 1.0), 60.);
+    let marble = glass.with_absorption(Vec3::new(1.0, 0.55, 0.3), 15.);
+    let light = Surface::new(Vec3::new(0.8, 0.8, 0.8), 0.0);
+    let dark = Surface::new(Vec3::new(0.2, 0.2, 0.25), 0.0);
+    let center = Vec3::new(0., -10., 0.);
+    let (bubble, ball) = (
+        center + Vec3::new(12., 12., -10.),
+        center + Vec3::new(-8., -12., 4.),
+    );
+    let mut scene = Scene::new(
+        move |p: Vec3| {
+            let check = (p.x / 20.).floor() + (p.z / 20.).floor();
+            let floor = if check.rem_euclid(2.) < 1. {
+                light
+            } else {
+                dark
+            };
+            let shell = intersect(
+                sphere(p, center, 40., glass),
+                invert(sphere(p, center, 36., glass)),
+            );
+            let water = intersect(
+                intersect(
+                    sphere(p, center, 36., water),
+                    invert(sphere(p, bubble, 9., water)),
+                ),
+                invert(sphere(p, ball, 13., water)),
+            );
+            union(
+                union(union(shell, water), sphere(p, ball, 13., marble)),
+                sphere(p, Vec3::new(0., -10050., 0.), 10000., floor),
+            )
+        },
+        vec![Light::area(
+            Vec3::new(-200., 300., -200.),
+            20.,
+            Vec3::new(1.0, 0.95, 0.9),
+        )],
+    );
+    scene.sky = Some(gradient_sky());
+    scene
+}
+
+// A glass prism in front of a checkered wall, with exaggerated dispersion
+// that fringes the edges seen through it with color when rendered with
+// Settings::dispersion
+pub fn prism() -> Scene {
+    let glass = Surface::new(Vec3::one(), 0.0)
+        .with_refraction(1.6)
+        .with_dispersion(0.015);
+    let light = Surface::new(Vec3::new(0.9, 0.9, 0.9), 0.0);
+    let dark = Surface::new(Vec3::new(0.05, 0.05, 0.05), 0.0);
+    let mut scene = Scene::new(
+        move |p: Vec3| {
+            // Triangular, standing on one end with a corner towards the eye
+            let mut prism = (p.y + 5.).abs() - 45.;
+            for k in 0..3 {
+                let angle = (90. + 120. * k as f32).to_radians();
+                let normal = Vec3::new(angle.cos(), 0., angle.sin());
+                prism = prism.max(p.dot(normal) - 18.);
+            }
+            let check = (p.x / 20.).floor() + (p.y / 20.).floor();
+            let wall = if check.rem_euclid(2.) < 1. {
+                light
+            } else {
+                dark
+            };
+            union(
+                Sample {
+                    distance: prism,
+                    surface: glass,
+                },
+                union(
+                    Sample {
+                        distance: 150. - p.z,
+                        surface: wall,
+                    },
+                    sphere(p, Vec3::new(0., -10050., 0.), 10000., light),
+                ),
+            )
+        },
+        vec![Light::area(
+            Vec3::new(-100., 200., -300.),
+            20.,
+            Vec3::new(1.0, 1.0, 1.0),
+        )],
+    );
+    scene.sky = Some(gradient_sky());
+    scene
+}
+
+// The scenes --bench totals, kept the same so that totals can be compared
+// across commits
+pub fn bench() -> Vec<(&'static str, Scene)> {
+    vec![
+        ("simple", simple()),
+        ("many-primitives", many_primitives()),
+        ("fractal", fractal()),
+        ("displacement", displacement()),
+    ]
+}
+
+// Timed by --bench after the total, for features added since
+pub fn bench_extra() -> Vec<(&'static str, Scene)> {
+    vec![
+        ("graph-tree", many_primitives_graph()),
+        ("graph-compiled", compiled(many_primitives_graph())),
+        ("generated", compiled(generated())),
+    ]
+}
+
+pub fn by_name(name: &str) -> Option<Scene> {
+    match name {
+        "default" => Some(default()),
+        "simple" => Some(simple()),
+        "many-primitives" => Some(many_primitives()),
+        "fractal" => Some(fractal()),
+        "displacement" => Some(displacement()),
+        "graph" => Some(compiled(graph())),
+        "glossy" => Some(glossy()),
+        "outdoor" => Some(outdoor()),
+        "mirrors" => Some(mirrors()),
+        "rust" => Some(rust()),
+        "worn" => Some(worn()),
+        "title" => Some(title()),
+        "generated" => Some(compiled(generated())),
+        "subsurface" => Some(subsurface()),
+        "bump" => Some(bump()),
+        "visibility" => Some(visibility()),
+        "shadow-catcher" => Some(shadow_catcher()),
+        "interior" => Some(interior()),
+        "glass" => Some(glass()),
+        "nested" => Some(nested()),
+        "prism" => Some(prism()),
+        _ => None,
+    }
+}
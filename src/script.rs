@@ -0,0 +1,487 @@
+use std::fmt;
+
+use ultraviolet::Vec3;
+
+use crate::distfield::{Sample, Sdf, Surface};
+use crate::noise;
+
+// A tiny expression language for prototyping distance fields, e.g.
+//
+//     let d = length(p - vec3(0, 0, 0)) - 50;
+//     let color = vec3(1, 0.8, 0.4);
+//     d + sin(p.x * 0.2) * 2
+//
+// The final expression is the distance. Optional `color`, `reflectivity`,
+// `roughness` and `emission` bindings set the surface, and can vary with p
+// like the distance, such as with `fbm(p * scale, octaves)` noise, which
+// takes at most 16 octaves. Types are checked when parsing, so evaluation
+// per sample can not fail.
+
+#[derive(Debug)]
+pub struct ScriptError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Type {
+    Num,
+    Vec,
+}
+
+#[derive(Clone, Copy)]
+enum Value {
+    Num(f32),
+    Vec(Vec3),
+}
+
+impl Value {
+    fn num(self) -> f32 {
+        match self {
+            Value::Num(n) => n,
+            Value::Vec(v) => v.x,
+        }
+    }
+
+    fn vec(self) -> Vec3 {
+        match self {
+            Value::Num(n) => Vec3::broadcast(n),
+            Value::Vec(v) => v,
+        }
+    }
+
+    fn map(self, f: impl Fn(f32) -> f32) -> Value {
+        match self {
+            Value::Num(n) => Value::Num(f(n)),
+            Value::Vec(v) => Value::Vec(Vec3::new(f(v.x), f(v.y), f(v.z))),
+        }
+    }
+
+    fn zip(self, other: Value, f: impl Fn(f32, f32) -> f32) -> Value {
+        match (self, other) {
+            (Value::Num(a), Value::Num(b)) => Value::Num(f(a, b)),
+            (a, b) => {
+                let (a, b) = (a.vec(), b.vec());
+                Value::Vec(Vec3::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z)))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Func {
+    Vec3,
+    Length,
+    Dot,
+    Normalize,
+    Abs,
+    Sin,
+    Cos,
+    Sqrt,
+    Floor,
+    Fract,
+    Min,
+    Max,
+    Mod,
+    Clamp,
+    Mix,
+    Smin,
+    Fbm,
+}
+
+// Finer octaves than this are below what floats can resolve, and evaluation
+// would otherwise take as long as the script asks for every sample
+const MAX_OCTAVES: u32 = 16;
+
+impl Func {
+    fn lookup(name: &str) -> Option<Func> {
+        Some(match name {
+            "vec3" => Func::Vec3,
+            "length" => Func::Length,
+            "dot" => Func::Dot,
+            "normalize" => Func::Normalize,
+            "abs" => Func::Abs,
+            "sin" => Func::Sin,
+            "cos" => Func::Cos,
+            "sqrt" => Func::Sqrt,
+            "floor" => Func::Floor,
+            "fract" => Func::Fract,
+            "min" => Func::Min,
+            "max" => Func::Max,
+            "mod" => Func::Mod,
+            "clamp" => Func::Clamp,
+            "mix" => Func::Mix,
+            "smin" => Func::Smin,
+            "fbm" => Func::Fbm,
+            _ => return None,
+        })
+    }
+
+    fn check(self, args: &[Type]) -> Result<Type, String> {
+        use Type::*;
+        let widest = if args.contains(&Vec) { Vec } else { Num };
+        let result = match (self, args) {
+            (Func::Vec3, [Num, Num, Num]) => Vec,
+            (Func::Length, [Vec]) => Num,
+            (Func::Dot, [Vec, Vec]) => Num,
+            (Func::Normalize, [Vec]) => Vec,
+            (Func::Abs | Func::Sin | Func::Cos | Func::Sqrt | Func::Floor | Func::Fract, [t]) => *t,
+            (Func::Min | Func::Max | Func::Mod, [_, _]) => widest,
+            (Func::Clamp, [t, Num, Num]) => *t,
+            (Func::Mix, [_, _, Num]) => widest,
+            (Func::Smin, [Num, Num, Num]) => Num,
+            (Func::Fbm, [Vec, Num]) => Num,
+            _ => return Err(format!("invalid arguments {:?} for {:?}", args, self)),
+        };
+        Ok(result)
+    }
+
+    fn eval(self, args: &[Value]) -> Value {
+        match self {
+            Func::Vec3 => Value::Vec(Vec3::new(args[0].num(), args[1].num(), args[2].num())),
+            Func::Length => Value::Num(args[0].vec().mag()),
+            Func::Dot => Value::Num(args[0].vec().dot(args[1].vec())),
+            Func::Normalize => Value::Vec(args[0].vec().normalized()),
+            Func::Abs => args[0].map(f32::abs),
+            Func::Sin => args[0].map(f32::sin),
+            Func::Cos => args[0].map(f32::cos),
+            Func::Sqrt => args[0].map(f32::sqrt),
+            Func::Floor => args[0].map(f32::floor),
+            Func::Fract => args[0].map(|x| x - x.floor()),
+            Func::Min => args[0].zip(args[1], f32::min),
+            Func::Max => args[0].zip(args[1], f32::max),
+            Func::Mod => args[0].zip(args[1], f32::rem_euclid),
+            Func::Clamp => {
+                let (lo, hi) = (args[1].num(), args[2].num());
+                args[0].map(|x| x.clamp(lo, hi))
+            }
+            Func::Mix => {
+                let t = args[2].num();
+                args[0].zip(args[1], |a, b| a + (b - a) * t)
+            }
+            Func::Smin => {
+                let (a, b, k) = (args[0].num(), args[1].num(), args[2].num());
+                let h = (0.5 + 0.5 * (b - a) / k).clamp(0., 1.);
+                Value::Num(b + (a - b) * h - k * h * (1. - h))
+            }
+            Func::Fbm => Value::Num(noise::fbm(
+                args[0].vec(),
+                (args[1].num() as u32).min(MAX_OCTAVES),
+            )),
+        }
+    }
+}
+
+enum Expr {
+    Num(f32),
+    Var(usize),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+    Field(Box<Expr>, usize),
+}
+
+impl Expr {
+    fn eval(&self, vars: &[Value]) -> Value {
+        match self {
+            Expr::Num(n) => Value::Num(*n),
+            Expr::Var(slot) => vars[*slot],
+            Expr::Neg(e) => e.eval(vars).map(|x| -x),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(vars), b.eval(vars));
+                match op {
+                    '+' => a.zip(b, |a, b| a + b),
+                    '-' => a.zip(b, |a, b| a - b),
+                    '*' => a.zip(b, |a, b| a * b),
+                    _ => a.zip(b, |a, b| a / b),
+                }
+            }
+            Expr::Call(func, args) => {
+                let args: Vec<_> = args.iter().map(|arg| arg.eval(vars)).collect();
+                func.eval(&args)
+            }
+            Expr::Field(e, axis) => {
+                let v = e.eval(vars).vec();
+                Value::Num([v.x, v.y, v.z][*axis])
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Num(f32),
+    Ident(String),
+    Symbol(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ScriptError> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let mut chars = line.char_indices().peekable();
+        while let Some(&(start, c)) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c.is_ascii_digit() {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let number = line[start..end].parse().map_err(|_| ScriptError {
+                    line: line_no,
+                    message: format!("invalid number {}", &line[start..end]),
+                })?;
+                tokens.push((Token::Num(number), line_no));
+            } else if c.is_alphabetic() || c == '_' {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push((Token::Ident(line[start..end].to_string()), line_no));
+            } else if "()+-*/,;=.".contains(c) {
+                tokens.push((Token::Symbol(c), line_no));
+                chars.next();
+            } else {
+                return Err(ScriptError {
+                    line: line_no,
+                    message: format!("unexpected character {:?}", c),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    names: Vec<(String, Type)>,
+}
+
+impl Parser {
+    fn error(&self, message: impl Into<String>) -> ScriptError {
+        let line = self
+            .tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |(_, line)| *line);
+        ScriptError {
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ScriptError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", symbol)))
+        }
+    }
+
+    fn expr(&mut self) -> Result<(Expr, Type), ScriptError> {
+        let mut lhs = self.term()?;
+        while let Some(Token::Symbol(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.term()?;
+            lhs = binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<(Expr, Type), ScriptError> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Symbol(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            lhs = binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<(Expr, Type), ScriptError> {
+        if self.eat('-') {
+            let (e, t) = self.unary()?;
+            return Ok((Expr::Neg(Box::new(e)), t));
+        }
+        let (mut e, mut t) = self.atom()?;
+        while self.eat('.') {
+            let axis = match self.next() {
+                Some(Token::Ident(field)) if t == Type::Vec => match field.as_str() {
+                    "x" => 0,
+                    "y" => 1,
+                    "z" => 2,
+                    _ => return Err(self.error(format!("unknown field {}", field))),
+                },
+                _ => return Err(self.error("expected x, y or z on a vec3")),
+            };
+            e = Expr::Field(Box::new(e), axis);
+            t = Type::Num;
+        }
+        Ok((e, t))
+    }
+
+    fn atom(&mut self) -> Result<(Expr, Type), ScriptError> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok((Expr::Num(n), Type::Num)),
+            Some(Token::Symbol('(')) => {
+                let e = self.expr()?;
+                self.expect(')')?;
+                Ok(e)
+            }
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::Symbol('(')) => {
+                let func = Func::lookup(&name)
+                    .ok_or_else(|| self.error(format!("unknown function {}", name)))?;
+                self.pos += 1;
+                let mut args = Vec::new();
+                let mut types = Vec::new();
+                if !self.eat(')') {
+                    loop {
+                        let (e, t) = self.expr()?;
+                        args.push(e);
+                        types.push(t);
+                        if self.eat(')') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                let t = func.check(&types).map_err(|message| self.error(message))?;
+                Ok((Expr::Call(func, args), t))
+            }
+            Some(Token::Ident(name)) => {
+                let slot = self
+                    .names
+                    .iter()
+                    .rposition(|(n, _)| *n == name)
+                    .ok_or_else(|| self.error(format!("unknown variable {}", name)))?;
+                Ok((Expr::Var(slot), self.names[slot].1))
+            }
+            _ => Err(self.error("expected an expression")),
+        }
+    }
+}
+
+fn binary(op: char, (a, ta): (Expr, Type), (b, tb): (Expr, Type)) -> (Expr, Type) {
+    let t = if ta == Type::Vec || tb == Type::Vec {
+        Type::Vec
+    } else {
+        Type::Num
+    };
+    (Expr::Binary(op, Box::new(a), Box::new(b)), t)
+}
+
+pub struct Script {
+    lets: Vec<Expr>,
+    distance: Expr,
+    color: Option<usize>,
+    reflectivity: Option<usize>,
+    roughness: Option<usize>,
+    emission: Option<usize>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Self, ScriptError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            names: vec![("p".into(), Type::Vec)],
+        };
+        let mut lets = Vec::new();
+        while parser.peek() == Some(&Token::Ident("let".into())) {
+            parser.pos += 1;
+            let name = match parser.next() {
+                Some(Token::Ident(name)) => name,
+                _ => return Err(parser.error("expected a name after let")),
+            };
+            parser.expect('=')?;
+            let (e, t) = parser.expr()?;
+            parser.expect(';')?;
+            lets.push(e);
+            parser.names.push((name, t));
+        }
+        let (distance, t) = parser.expr()?;
+        if t != Type::Num {
+            return Err(parser.error("the distance must be a number"));
+        }
+        if parser.pos < parser.tokens.len() {
+            return Err(parser.error("unexpected input after the distance"));
+        }
+
+        let binding = |name: &str, t: Type| -> Result<Option<usize>, ScriptError> {
+            match parser.names.iter().rposition(|(n, _)| n == name) {
+                Some(slot) if parser.names[slot].1 == t => Ok(Some(slot)),
+                Some(_) => Err(parser.error(format!("{} has the wrong type", name))),
+                None => Ok(None),
+            }
+        };
+        Ok(Self {
+            color: binding("color", Type::Vec)?,
+            reflectivity: binding("reflectivity", Type::Num)?,
+            roughness: binding("roughness", Type::Num)?,
+            emission: binding("emission", Type::Vec)?,
+            lets,
+            distance,
+        })
+    }
+}
+
+impl Sdf for Script {
+    fn sample(&self, p: Vec3) -> Sample {
+        let mut vars = Vec::with_capacity(self.lets.len() + 1);
+        vars.push(Value::Vec(p));
+        for e in self.lets.iter() {
+            let value = e.eval(&vars);
+            vars.push(value);
+        }
+        let color = self
+            .color
+            .map_or(Vec3::new(0.8, 0.8, 0.8), |slot| vars[slot].vec());
+        let reflectivity = self.reflectivity.map_or(0., |slot| vars[slot].num());
+        let roughness = self.roughness.map_or(0., |slot| vars[slot].num());
+        let emission = self.emission.map_or(Vec3::zero(), |slot| vars[slot].vec());
+        Sample {
+            distance: self.distance.eval(&vars).num(),
+            surface: Surface::new(color, reflectivity)
+                .with_roughness(roughness)
+                .with_emission(emission),
+        }
+    }
+}
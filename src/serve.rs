@@ -0,0 +1,256 @@
+use std::io::{self, BufRead, BufReader, Cursor, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use image::{DynamicImage, ImageOutputFormat, Rgba32FImage};
+
+use raycast::distfield::ObjectId;
+use raycast::{pick, scenes, Scene, Settings};
+
+use crate::{render, render_hdr};
+
+// Largest scene file that can be posted
+const MAX_SCENE_BYTES: u64 = 16 << 20;
+// Longest request line and headers together
+const MAX_HEAD_BYTES: u64 = 8192;
+// Requests are handled one at a time, so clients that stop sending or
+// reading are dropped after this long rather than holding up the rest
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Request {
+    scene: String,
+    width: u32,
+    height: u32,
+    format: ImageOutputFormat,
+    settings: Settings,
+    // Pixel to pick, for /pick
+    x: u32,
+    y: u32,
+}
+
+impl Request {
+    fn parse(query: &str) -> Result<Self> {
+        let mut request = Self {
+            scene: "default".into(),
+            width: 640,
+            height: 480,
+            format: ImageOutputFormat::Png,
+            settings: Settings::default(),
+            x: 0,
+            y: 0,
+        };
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "scene" => request.scene = value.into(),
+                "width" => request.width = value.parse()?,
+                "height" => request.height = value.parse()?,
+                "samples" => request.settings.samples = value.parse()?,
+                "seed" => request.settings.seed = value.parse()?,
+                "x" => request.x = value.parse()?,
+                "y" => request.y = value.parse()?,
+                "format" => {
+                    request.format = match value {
+                        "png" => ImageOutputFormat::Png,
+                        "exr" => ImageOutputFormat::OpenExr,
+                        _ => bail!("unsupported format: {}", value),
+                    }
+                }
+                _ => bail!("unknown parameter: {}", key),
+            }
+        }
+        // Widened, as the size comes straight from the query
+        let pixels = request.width as u64 * request.height as u64;
+        if pixels == 0 || pixels > 1 << 24 {
+            bail!("invalid resolution");
+        }
+        if request.x >= request.width || request.y >= request.height {
+            bail!("pixel outside of the image");
+        }
+        if !(1..=256).contains(&request.settings.samples) {
+            bail!("invalid sample count");
+        }
+        Ok(request)
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+fn timed_out(err: &io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+// Reads the request line and the content length from the headers, or None
+// if they don't end within MAX_HEAD_BYTES
+fn read_head(reader: &mut impl BufRead) -> io::Result<Option<(String, Option<u64>)>> {
+    let mut head = reader.take(MAX_HEAD_BYTES);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    // Only the length of a posted scene matters in the headers
+    let mut content_length = None;
+    let mut header = String::new();
+    while head.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<u64>().ok();
+            }
+        }
+        header.clear();
+    }
+    if head.limit() == 0 {
+        return Ok(None);
+    }
+    Ok(Some((request_line, content_length)))
+}
+
+fn handle(mut stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (request_line, content_length) = match read_head(&mut reader) {
+        Ok(Some(head)) => head,
+        Ok(None) => {
+            return respond(
+                &mut stream,
+                "431 Request Header Fields Too Large",
+                "text/plain",
+                b"request line and headers are too long",
+            )
+        }
+        Err(err) if timed_out(&err) => {
+            return respond(
+                &mut stream,
+                "408 Request Timeout",
+                "text/plain",
+                b"timed out",
+            )
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    // Scenes are rendered by name, or posted as the text of a scene file
+    let (method, target) = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        [method @ ("GET" | "POST"), target, _] => (method, target),
+        _ => {
+            return respond(
+                &mut stream,
+                "400 Bad Request",
+                "text/plain",
+                b"malformed request",
+            )
+        }
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != "/render" && path != "/pick" {
+        return respond(&mut stream, "404 Not Found", "text/plain", b"unknown path");
+    }
+
+    let request = match Request::parse(query) {
+        Ok(request) => request,
+        Err(err) => {
+            return respond(
+                &mut stream,
+                "400 Bad Request",
+                "text/plain",
+                err.to_string().as_bytes(),
+            )
+        }
+    };
+    let scene = if method == "POST" {
+        let length = match content_length {
+            Some(length) if length <= MAX_SCENE_BYTES => length,
+            _ => {
+                return respond(
+                    &mut stream,
+                    "400 Bad Request",
+                    "text/plain",
+                    b"posted scenes need a content length of at most 16 MiB",
+                )
+            }
+        };
+        let mut text = String::new();
+        match reader.take(length).read_to_string(&mut text) {
+            Ok(_) => {}
+            Err(err) if timed_out(&err) => {
+                return respond(
+                    &mut stream,
+                    "408 Request Timeout",
+                    "text/plain",
+                    b"timed out",
+                )
+            }
+            Err(err) => return Err(err.into()),
+        }
+        match Scene::from_json(&text) {
+            Ok(scene) => scene,
+            Err(err) => {
+                return respond(
+                    &mut stream,
+                    "400 Bad Request",
+                    "text/plain",
+                    err.to_string().as_bytes(),
+                )
+            }
+        }
+    } else {
+        match scenes::by_name(&request.scene) {
+            Some(scene) => scene,
+            None => return respond(&mut stream, "404 Not Found", "text/plain", b"unknown scene"),
+        }
+    };
+
+    if path == "/pick" {
+        // The id of the object under the pixel, or "none"
+        let body = match pick(
+            &request.settings,
+            &scene,
+            (request.width, request.height),
+            request.x,
+            request.y,
+        ) {
+            Some(ObjectId(id)) => id.to_string(),
+            None => "none".into(),
+        };
+        return respond(&mut stream, "200 OK", "text/plain", body.as_bytes());
+    }
+
+    let (width, height) = (request.width, request.height);
+    let (img, content_type) = match request.format {
+        // Keeps the full range of the render, as for .exr outputs
+        ImageOutputFormat::OpenExr => {
+            let fb = render_hdr(&scene, &request.settings, width, height, false);
+            let img = Rgba32FImage::from_raw(width, height, fb.to_rgba_f32()).unwrap();
+            (DynamicImage::ImageRgba32F(img), "image/x-exr")
+        }
+        _ => {
+            let img = render(&scene, &request.settings, width, height, false);
+            (DynamicImage::ImageRgba8(img), "image/png")
+        }
+    };
+    let mut body = Cursor::new(Vec::new());
+    img.write_to(&mut body, request.format)?;
+    respond(&mut stream, "200 OK", content_type, body.get_ref())
+}
+
+pub fn serve(addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr).map_err(|err| anyhow!("{}: {}", addr, err))?;
+    println!("listening on http://{}/render", listener.local_addr()?);
+    for stream in listener.incoming() {
+        // Renders already use every core, so requests are handled one at a time
+        if let Err(err) = stream.map_err(Into::into).and_then(handle) {
+            eprintln!("request failed: {}", err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+use std::f32::consts::PI;
+
+use ultraviolet::{Lerp, Vec3};
+
+use crate::color::xyy_to_rgb;
+use crate::json::Json;
+use crate::Light;
+
+// Preetham et al., "A Practical Analytic Model for Daylight" (1999). Colors
+// are scaled down so that a clear sky ends up roughly in [0, 1].
+const LUMINANCE_SCALE: f32 = 1. / 12.;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Preetham {
+    sun: Vec3,
+    turbidity: f32,
+    // Perez coefficients for Y, x and y
+    perez: [[f32; 5]; 3],
+    // Zenith values for Y, x and y, divided by the Perez function at the zenith
+    zenith: [f32; 3],
+}
+
+// Blend from the horizon color up to the zenith and down to the ground
+#[derive(Clone, Copy, Debug)]
+pub struct Gradient {
+    zenith: Vec3,
+    horizon: Vec3,
+    ground: Vec3,
+    sun: Option<SunDisk>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SunDisk {
+    dir: Vec3,
+    cos_radius: f32,
+    color: Vec3,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Sky {
+    Preetham(Preetham),
+    Gradient(Gradient),
+}
+
+impl Sky {
+    // Sky for the given direction towards the sun (with y up), and turbidity
+    // ranging from 2 (very clear) to around 10 (hazy)
+    pub fn preetham(sun: Vec3, turbidity: f32) -> Self {
+        Self::preetham_normalized(sun.normalized(), turbidity)
+    }
+
+    fn preetham_normalized(sun: Vec3, turbidity: f32) -> Self {
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let theta_s = sun.y.clamp(0., 1.).acos();
+        let (t2, th2, th3) = (t * t, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = (0.00166 * th3 - 0.00375 * th2 + 0.00209 * theta_s) * t2
+            + (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * theta_s + 0.00394) * t
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * theta_s + 0.25886);
+        let zenith_y = (0.00275 * th3 - 0.00610 * th2 + 0.00317 * theta_s) * t2
+            + (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * theta_s + 0.00516) * t
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * theta_s + 0.26688);
+
+        let mut zenith = [zenith_luminance.max(0.), zenith_x, zenith_y];
+        for (z, coefficients) in zenith.iter_mut().zip(perez.iter()) {
+            *z /= perez_function(coefficients, 0., theta_s);
+        }
+
+        Sky::Preetham(Preetham {
+            sun,
+            turbidity,
+            perez,
+            zenith,
+        })
+    }
+
+    pub fn gradient(zenith: Vec3, horizon: Vec3, ground: Vec3) -> Self {
+        Sky::Gradient(Gradient {
+            zenith,
+            horizon,
+            ground,
+            sun: None,
+        })
+    }
+
+    // Adds a visible sun in the given direction to a gradient sky, with an
+    // angular radius in radians. Analytic skies already have their own sun.
+    pub fn with_sun(self, dir: Vec3, radius: f32, color: Vec3) -> Self {
+        match self {
+            Sky::Gradient(gradient) => Sky::Gradient(Gradient {
+                sun: Some(SunDisk {
+                    dir: dir.normalized(),
+                    cos_radius: radius.cos(),
+                    color,
+                }),
+                ..gradient
+            }),
+            sky => sky,
+        }
+    }
+
+    pub fn color(&self, dir: Vec3) -> Vec3 {
+        match self {
+            Sky::Preetham(sky) => {
+                // Below the horizon, continue the horizon color
+                let cos_theta = dir.y.max(0.001);
+                let gamma = dir.dot(sky.sun).clamp(-1., 1.).acos();
+                let theta = cos_theta.acos();
+                let [y, x, yy] =
+                    [0, 1, 2].map(|i| sky.zenith[i] * perez_function(&sky.perez[i], theta, gamma));
+                xyy_to_rgb(x, yy, y * LUMINANCE_SCALE)
+            }
+            Sky::Gradient(sky) => {
+                // Changes quickly near the horizon, as in a real sky
+                let color = if dir.y >= 0. {
+                    sky.horizon.lerp(sky.zenith, dir.y.sqrt())
+                } else {
+                    sky.horizon.lerp(sky.ground, (-dir.y).sqrt())
+                };
+                match sky.sun {
+                    Some(sun) if dir.dot(sun.dir) >= sun.cos_radius => sun.color,
+                    _ => color,
+                }
+            }
+        }
+    }
+
+    // Directional light for the sun to go with the sky, or None if it has
+    // no sun. Analytic skies color it by its passage through the atmosphere,
+    // gradient skies by the color of the sun disk.
+    pub fn sun_light(&self, intensity: f32) -> Option<Light> {
+        match self {
+            Sky::Preetham(sky) => {
+                let color = sun_transmittance(sky.sun, sky.turbidity) * intensity;
+                Some(Light::directional(sky.sun, color))
+            }
+            Sky::Gradient(sky) => sky
+                .sun
+                .map(|sun| Light::directional(sun.dir, sun.color * intensity)),
+        }
+    }
+}
+
+// As saved in scene files. Analytic skies are stored as their parameters,
+// and rebuilt from them when loaded.
+impl Sky {
+    pub(crate) fn to_json(self) -> Json {
+        match self {
+            Sky::Preetham(sky) => Json::object([
+                ("type", "preetham".into()),
+                ("sun", sky.sun.into()),
+                ("turbidity", sky.turbidity.into()),
+            ]),
+            Sky::Gradient(sky) => Json::object([
+                ("type", "gradient".into()),
+                ("zenith", sky.zenith.into()),
+                ("horizon", sky.horizon.into()),
+                ("ground", sky.ground.into()),
+                (
+                    "sun",
+                    sky.sun
+                        .map(|sun| {
+                            Json::object([
+                                ("direction", sun.dir.into()),
+                                ("cos_radius", sun.cos_radius.into()),
+                                ("color", sun.color.into()),
+                            ])
+                        })
+                        .into(),
+                ),
+            ]),
+        }
+    }
+
+    pub(crate) fn from_json(json: &Json) -> Result<Self, String> {
+        match json.required("type", Json::str)? {
+            "preetham" => Ok(Self::preetham_normalized(
+                json.required("sun", Json::vec3)?,
+                json.required("turbidity", Json::f32)?,
+            )),
+            "gradient" => {
+                let sun = match json.optional("sun", Some)? {
+                    Some(sun) => Some(SunDisk {
+                        dir: sun.required("direction", Json::direction)?,
+                        cos_radius: sun.required("cos_radius", Json::f32)?,
+                        color: sun.required("color", Json::vec3)?,
+                    }),
+                    None => None,
+                };
+                Ok(Sky::Gradient(Gradient {
+                    zenith: json.required("zenith", Json::vec3)?,
+                    horizon: json.required("horizon", Json::vec3)?,
+                    ground: json.required("ground", Json::vec3)?,
+                    sun,
+                }))
+            }
+            sky => Err(format!("unknown sky: {}", sky)),
+        }
+    }
+}
+
+fn perez_function(c: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+    let cos_gamma = gamma.cos();
+    (1. + c[0] * (c[1] / theta.cos().max(0.001)).exp())
+        * (1. + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+// Rayleigh and aerosol (Angstrom) extinction along the sun's path through
+// the atmosphere, from the appendix of the Preetham paper, evaluated at
+// representative wavelengths for red, green and blue
+fn sun_transmittance(sun: Vec3, turbidity: f32) -> Vec3 {
+    let theta = sun.y.clamp(0., 1.).acos();
+    let air_mass = 1. / (theta.cos() + 0.15 * (93.885 - theta.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let [r, g, b] = [0.68f32, 0.55, 0.44].map(|lambda| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+        rayleigh * aerosol
+    });
+    Vec3::new(r, g, b)
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Cel shading for a whole render, with the diffuse light from each light cut
+// into flat bands, and optionally ink outlines this many pixels wide where
+// the depth or normal seen changes sharply. Surfaces can also be banded on
+// their own, see Surface::with_toon.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Toon {
+    pub bands: u32,
+    pub outline: Option<f32>,
+}
+
+impl Toon {
+    pub fn new(bands: u32) -> Self {
+        Self {
+            bands,
+            outline: None,
+        }
+    }
+}
+
+// Rounds diffuse lighting in [0, 1] up to the next of the bands, so only
+// unlit parts end up black
+pub fn quantize(diffuse: f32, bands: u32) -> f32 {
+    let bands = bands.max(1) as f32;
+    (diffuse * bands).ceil() / bands
+}
+
+impl fmt::Display for Toon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.bands)?;
+        if let Some(outline) = self.outline {
+            write!(f, ":{}", outline)?;
+        }
+        Ok(())
+    }
+}
+
+// Parses "<bands>" or "<bands>:<outline width>"
+impl FromStr for Toon {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (bands, outline) = match s.split_once(':') {
+            Some((bands, outline)) => {
+                let outline = outline
+                    .parse()
+                    .map_err(|err| format!("invalid outline width: {}", err))?;
+                (bands, Some(outline))
+            }
+            None => (s, None),
+        };
+        let bands = match bands.parse() {
+            Ok(bands) if bands > 0 => bands,
+            _ => return Err(format!("invalid band count: {}", bands)),
+        };
+        Ok(Self { bands, outline })
+    }
+}
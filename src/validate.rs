@@ -0,0 +1,198 @@
+use std::fmt;
+
+use ultraviolet::Vec3;
+
+use crate::bytecode::Op;
+use crate::distfield::Surface;
+
+// Above this, displacement makes the field overestimate distances enough
+// that rays can step through surfaces
+const MAX_DISPLACEMENT_SLOPE: f32 = 1.;
+
+// Problems found by Scene::validate, which are likely to show up as
+// artifacts or missing objects rather than errors
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    // A direction or rotation that should have unit length
+    NotNormalized { what: &'static str, length: f32 },
+    // Displacement steep enough to break the distance estimate
+    SteepDisplacement { scale: f32, detail: f32 },
+    // A transform that collapses or mirrors its child
+    DegenerateTransform { scale: f32 },
+    // A primitive that covers nothing
+    EmptyPrimitive { what: &'static str, size: f32 },
+    // A material or light parameter outside of its useful range
+    OutOfRange { what: &'static str, value: f32 },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::NotNormalized { what, length } => {
+                write!(f, "{} has length {} instead of 1", what, length)
+            }
+            Warning::SteepDisplacement { scale, detail } => write!(
+                f,
+                "displacement with scale {} and detail {} is too steep for the distance estimate",
+                scale, detail
+            ),
+            Warning::DegenerateTransform { scale } => {
+                write!(f, "transform has a scale of {}", scale)
+            }
+            Warning::EmptyPrimitive { what, size } => write!(f, "{} has a size of {}", what, size),
+            Warning::OutOfRange { what, value } => {
+                write!(f, "{} is out of range at {}", what, value)
+            }
+        }
+    }
+}
+
+fn check_range(warnings: &mut Vec<Warning>, what: &'static str, value: f32, min: f32, max: f32) {
+    if !(min..=max).contains(&value) {
+        warnings.push(Warning::OutOfRange { what, value });
+    }
+}
+
+pub(crate) fn check_color(warnings: &mut Vec<Warning>, what: &'static str, color: Vec3) {
+    check_range(warnings, what, color.component_min(), 0., f32::INFINITY);
+}
+
+pub(crate) fn check_normalized(warnings: &mut Vec<Warning>, what: &'static str, length: f32) {
+    if (length - 1.).abs() > 1e-3 {
+        warnings.push(Warning::NotNormalized { what, length });
+    }
+}
+
+pub(crate) fn check_surface(warnings: &mut Vec<Warning>, surface: &Surface) {
+    let color = surface.color;
+    check_range(
+        warnings,
+        "surface color",
+        color.component_min(),
+        0.,
+        f32::INFINITY,
+    );
+    check_color(warnings, "emission", surface.emission);
+    check_range(warnings, "reflectivity", surface.reflectivity, 0., 1.);
+    check_range(warnings, "roughness", surface.roughness, 0., 1.);
+    if let Some(scatter) = surface.scatter {
+        check_range(
+            warnings,
+            "scatter depth",
+            scatter.depth,
+            f32::MIN_POSITIVE,
+            f32::INFINITY,
+        );
+    }
+    if let Some(ior) = surface.ior {
+        check_range(warnings, "index of refraction", ior, 1., f32::INFINITY);
+    }
+    check_range(
+        warnings,
+        "dispersion",
+        surface.dispersion,
+        0.,
+        f32::INFINITY,
+    );
+    if let Some(absorption) = surface.absorption {
+        check_range(
+            warnings,
+            "absorption color",
+            absorption.color.component_min(),
+            0.,
+            1.,
+        );
+        check_range(
+            warnings,
+            "absorption color",
+            absorption.color.component_max(),
+            0.,
+            1.,
+        );
+        check_range(
+            warnings,
+            "absorption depth",
+            absorption.depth,
+            f32::MIN_POSITIVE,
+            f32::INFINITY,
+        );
+    }
+    if let Some(bump) = surface.bump {
+        check_range(
+            warnings,
+            "bump scale",
+            bump.scale,
+            f32::MIN_POSITIVE,
+            f32::INFINITY,
+        );
+    }
+    if surface.toon == Some(0) {
+        warnings.push(Warning::OutOfRange {
+            what: "toon bands",
+            value: 0.,
+        });
+    }
+    if let Some(wear) = surface.wear {
+        check_range(
+            warnings,
+            "wear radius",
+            wear.radius,
+            f32::MIN_POSITIVE,
+            f32::INFINITY,
+        );
+        check_color(warnings, "wear edge color", wear.edge);
+        check_color(warnings, "wear cavity color", wear.cavity);
+    }
+}
+
+pub(crate) fn check_ops(warnings: &mut Vec<Warning>, ops: &[Op]) {
+    for op in ops {
+        match *op {
+            Op::Sphere {
+                radius, surface, ..
+            } => {
+                if radius <= 0. {
+                    warnings.push(Warning::EmptyPrimitive {
+                        what: "sphere",
+                        size: radius,
+                    });
+                }
+                check_surface(warnings, &surface);
+            }
+            Op::Capsule {
+                radius, surface, ..
+            } => {
+                if radius <= 0. {
+                    warnings.push(Warning::EmptyPrimitive {
+                        what: "capsule",
+                        size: radius,
+                    });
+                }
+                check_surface(warnings, &surface);
+            }
+            Op::Mandelbulb { scale, surface, .. } => {
+                if scale <= 0. {
+                    warnings.push(Warning::EmptyPrimitive {
+                        what: "mandelbulb",
+                        size: scale,
+                    });
+                }
+                check_surface(warnings, &surface);
+            }
+            Op::Displace { scale, detail } => {
+                if (scale * detail).abs() > MAX_DISPLACEMENT_SLOPE {
+                    warnings.push(Warning::SteepDisplacement { scale, detail });
+                }
+            }
+            Op::PushTransform {
+                rotation, scale, ..
+            } => {
+                if !(scale > 0. && scale.is_finite()) {
+                    warnings.push(Warning::DegenerateTransform { scale });
+                }
+                check_normalized(warnings, "transform rotation", rotation.mag());
+            }
+            Op::Union | Op::Intersect | Op::Invert | Op::PushWarp | Op::PopTransform { .. } => {}
+        }
+    }
+}
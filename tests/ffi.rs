@@ -0,0 +1,137 @@
+// Calls the C interface the way other languages would, with scenes as the
+// text of scene files, and checks it gives what the Rust API does. Run with
+//
+//     cargo test --features ffi --test ffi
+#![cfg(feature = "ffi")]
+
+use std::ffi::{CStr, CString};
+use std::path::Path;
+use std::ptr;
+
+use ultraviolet::Vec3;
+
+use raycast::ffi::{self, RayHit};
+use raycast::{query, render_rgba, scenes, Scene, Settings};
+
+// The built-in scene saved to a file, loaded again and as its text. Tests
+// run in parallel, so each saves to its own file.
+fn saved(name: &str, file: &str) -> (Scene, CString) {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("ffi-{}.json", file));
+    scenes::by_name(name).unwrap().save(&path).unwrap();
+    let json = CString::new(std::fs::read_to_string(&path).unwrap()).unwrap();
+    (Scene::load(&path).unwrap(), json)
+}
+
+fn last_error() -> String {
+    unsafe { CStr::from_ptr(ffi::last_error_message()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn renders_like_the_library() {
+    let (scene, json) = saved("generated", "renders");
+    let options = ffi::RenderOptions {
+        width: 32,
+        height: 24,
+        samples: 2,
+        ..ffi::render_options_default()
+    };
+    let mut pixels = vec![0; 32 * 24 * 4];
+    let result = unsafe { ffi::render_scene(json.as_ptr(), &options, pixels.as_mut_ptr()) };
+    assert_eq!(result, 0, "{}", last_error());
+
+    let settings = Settings {
+        samples: 2,
+        ..Settings::default()
+    };
+    assert!(pixels == render_rgba(&scene, &settings, 32, 24));
+}
+
+#[test]
+fn queries_like_the_library() {
+    let (scene, json) = saved("graph", "queries");
+    let handle = unsafe { ffi::scene_from_json(json.as_ptr()) };
+    assert!(!handle.is_null(), "{}", last_error());
+
+    let from = [0., 0., -100.];
+    // Not normalized, which the C interface does for the caller
+    let dir = [0.1, 0.2, 2.];
+    let mut hit = RayHit::default();
+    let found = unsafe { ffi::scene_cast_ray(handle, from.as_ptr(), dir.as_ptr(), &mut hit) };
+    let (from_v, dir_v) = (Vec3::from(from), Vec3::from(dir).normalized());
+    let expected = query::cast(&scene, from_v, dir_v).unwrap();
+    assert_eq!(found, 1);
+    assert_eq!(hit.p, *expected.p.as_array());
+    assert_eq!(hit.n, *expected.n.as_array());
+    assert_eq!(hit.distance, expected.distance);
+
+    let steps = unsafe { ffi::scene_ray_steps(handle, from.as_ptr(), dir.as_ptr()) };
+    assert_eq!(steps, query::steps(&scene, from_v, dir_v) as i64);
+
+    let mut out = [0.; 3];
+    let left = unsafe { ffi::scene_leave(handle, hit.p.as_ptr(), dir.as_ptr(), out.as_mut_ptr()) };
+    assert_eq!(left, 1);
+    assert_eq!(
+        out,
+        *query::leave(&scene, expected.p, dir_v).unwrap().as_array()
+    );
+
+    let result =
+        unsafe { ffi::scene_normal(handle, hit.p.as_ptr(), hit.distance, out.as_mut_ptr()) };
+    assert_eq!(result, 0);
+    assert_eq!(out, hit.n);
+
+    unsafe { ffi::scene_free(handle) };
+}
+
+#[test]
+fn reports_errors() {
+    let invalid = CString::new("{ \"version\": 1 }").unwrap();
+    assert!(unsafe { ffi::scene_from_json(invalid.as_ptr()) }.is_null());
+    assert_eq!(last_error(), "missing ops");
+
+    let options = ffi::render_options_default();
+    let result = unsafe { ffi::render_scene(invalid.as_ptr(), &options, ptr::null_mut()) };
+    assert_eq!(result, ffi::ERROR_INVALID_SCENE);
+
+    let (_, json) = saved("graph", "errors");
+    let mut pixels = [0; 4];
+    let options = ffi::RenderOptions {
+        width: 0,
+        ..options
+    };
+    let result = unsafe { ffi::render_scene(json.as_ptr(), &options, pixels.as_mut_ptr()) };
+    assert_eq!(result, ffi::ERROR_INVALID_ARGUMENT);
+
+    let handle = unsafe { ffi::scene_from_json(json.as_ptr()) };
+    let zero = [0.; 3];
+    let steps = unsafe { ffi::scene_ray_steps(handle, zero.as_ptr(), zero.as_ptr()) };
+    assert_eq!(steps, ffi::ERROR_INVALID_ARGUMENT as i64);
+    let steps = unsafe { ffi::scene_ray_steps(handle, ptr::null(), zero.as_ptr()) };
+    assert_eq!(steps, ffi::ERROR_NULL_POINTER as i64);
+    unsafe { ffi::scene_free(handle) };
+}
+
+#[test]
+fn malformed_programs_are_errors() {
+    // Would take a sample off an empty stack when rendered
+    let json = CString::new(
+        r#"{ "version": 1, "ops": [
+            { "op": "sphere", "center": [0, 0, 0], "radius": 1, "surface": {} },
+            { "op": "union" },
+            { "op": "sphere", "center": [0, 0, 0], "radius": 1, "surface": {} }
+        ] }"#,
+    )
+    .unwrap();
+    assert!(unsafe { ffi::scene_from_json(json.as_ptr()) }.is_null());
+
+    let options = ffi::RenderOptions {
+        width: 4,
+        height: 4,
+        ..ffi::render_options_default()
+    };
+    let mut pixels = [0; 4 * 4 * 4];
+    let result = unsafe { ffi::render_scene(json.as_ptr(), &options, pixels.as_mut_ptr()) };
+    assert_eq!(result, ffi::ERROR_INVALID_SCENE, "{}", last_error());
+}
@@ -0,0 +1,174 @@
+// Renders small versions of the built-in scenes and compares them with the
+// reference images in tests/golden, so that changes to ray marching or
+// shading can't silently change the output. After an intended change, look
+// at the differences and regenerate the references with
+//
+//     UPDATE_GOLDEN=1 cargo test --test golden
+#![cfg(feature = "cli")]
+
+use std::path::{Path, PathBuf};
+
+use image::RgbaImage;
+
+use raycast::{render_rgba, scenes, Settings};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+// A pixel counts as different when its weighted difference in 8 bit units
+// is above this, which leaves room for small floating point changes
+const PIXEL_TOLERANCE: f32 = 8.;
+// Fraction of pixels that may differ, for changes along edges
+const MAX_DIFFERENT: f32 = 0.01;
+// Limit on the mean difference over all pixels, for subtle global shifts
+const MAX_MEAN: f32 = 1.;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+// Difference between two pixels weighted by how visible each channel is,
+// including alpha
+fn pixel_difference(a: &[u8], b: &[u8]) -> f32 {
+    let weights = [0.2126, 0.7152, 0.0722];
+    let color: f32 = (0..3)
+        .map(|i| (a[i] as f32 - b[i] as f32).abs() * weights[i])
+        .sum();
+    color.max((a[3] as f32 - b[3] as f32).abs())
+}
+
+fn check(name: &str, settings: Settings) {
+    let scene = scenes::by_name(name).unwrap();
+    let pixels = render_rgba(&scene, &settings, WIDTH, HEIGHT);
+    let actual = RgbaImage::from_raw(WIDTH, HEIGHT, pixels).unwrap();
+    let path = golden_dir().join(format!("{}.png", name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&path).unwrap();
+        return;
+    }
+
+    let expected = image::open(&path)
+        .unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+        .into_rgba8();
+    assert_eq!(expected.dimensions(), actual.dimensions(), "{}", name);
+
+    let differences: Vec<f32> = expected
+        .pixels()
+        .zip(actual.pixels())
+        .map(|(a, b)| pixel_difference(&a.0, &b.0))
+        .collect();
+    let count = differences.len() as f32;
+    let different = differences.iter().filter(|&&d| d > PIXEL_TOLERANCE).count() as f32 / count;
+    let mean = differences.iter().sum::<f32>() / count;
+    if different > MAX_DIFFERENT || mean > MAX_MEAN {
+        // Keep the render around for comparison
+        let actual_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.png", name));
+        actual.save(&actual_path).unwrap();
+        panic!(
+            "{} differs from the reference in {:.1}% of pixels, with a mean difference of {:.2}, see {}",
+            name,
+            different * 100.,
+            mean,
+            actual_path.display()
+        );
+    }
+}
+
+#[test]
+fn simple() {
+    check("simple", Settings::default());
+}
+
+#[test]
+fn default() {
+    check("default", Settings::default());
+}
+
+#[test]
+fn glossy() {
+    check(
+        "glossy",
+        Settings {
+            samples: 4,
+            seed: 1,
+            ..Settings::default()
+        },
+    );
+}
+
+#[test]
+fn outdoor() {
+    check("outdoor", Settings::default());
+}
+
+#[test]
+fn shadow_catcher() {
+    check("shadow-catcher", Settings::default());
+}
+
+#[test]
+fn fractal() {
+    check("fractal", Settings::default());
+}
+
+#[test]
+fn displacement() {
+    check("displacement", Settings::default());
+}
+
+#[test]
+fn subsurface() {
+    check("subsurface", Settings::default());
+}
+
+#[test]
+fn bump() {
+    check("bump", Settings::default());
+}
+
+#[test]
+fn mirrors() {
+    check("mirrors", Settings::default());
+}
+
+#[test]
+fn rust() {
+    check("rust", Settings::default());
+}
+
+#[test]
+fn worn() {
+    check("worn", Settings::default());
+}
+
+#[test]
+fn title() {
+    check("title", Settings::default());
+}
+
+#[test]
+fn generated() {
+    check("generated", Settings::default());
+}
+
+#[test]
+fn glass() {
+    check("glass", Settings::default());
+}
+
+#[test]
+fn nested() {
+    check("nested", Settings::default());
+}
+
+#[test]
+fn prism() {
+    check(
+        "prism",
+        Settings {
+            dispersion: Some(8),
+            ..Settings::default()
+        },
+    );
+}
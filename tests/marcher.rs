@@ -0,0 +1,228 @@
+// Property tests for the ray marcher. Builds random scenes from primitives
+// under random transforms, casts random rays through them, and checks what
+// has to hold for any of them: rays finish within a bounded number of steps,
+// hits are just behind the surface, normals are unit length, and rays
+// leaving objects end up outside. Failures name the case, which is also the
+// seed its scene and rays are generated from.
+
+use std::f32::consts::PI;
+
+use ultraviolet::{Rotor3, Vec3};
+
+use raycast::distfield::{Sdf, Surface};
+use raycast::graph::{Capsule, Sphere, Transform, Union};
+use raycast::query;
+use raycast::rng::Rng;
+use raycast::Scene;
+
+const CASES: u64 = 500;
+const RAYS_PER_CASE: usize = 20;
+// Scenes fit in a box this far from the origin along each axis, with the
+// objects placed around the middle of it
+const HALF_SIZE: f32 = 100.;
+const OBJECT_SPREAD: f32 = 50.;
+// Slack for rounding in the checks, relative to the sizes involved
+const TOLERANCE: f32 = 1e-3;
+
+fn range(rng: &mut Rng, min: f32, max: f32) -> f32 {
+    min + (max - min) * rng.next_f32()
+}
+
+fn point(rng: &mut Rng, half_size: f32) -> Vec3 {
+    Vec3::new(
+        range(rng, -half_size, half_size),
+        range(rng, -half_size, half_size),
+        range(rng, -half_size, half_size),
+    )
+}
+
+// Towards where the objects are, uniform over the sphere, or along an axis
+// now and then, where the slabs of the bounds are parallel to the ray
+fn direction(rng: &mut Rng, from: Vec3) -> Vec3 {
+    let choice = rng.next_f32();
+    if choice < 0.1 {
+        let axis = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()][rng.next_u32() as usize % 3];
+        return if rng.next_f32() < 0.5 { axis } else { -axis };
+    }
+    if choice < 0.6 {
+        let towards = point(rng, OBJECT_SPREAD);
+        if towards != from {
+            return (towards - from).normalized();
+        }
+    }
+    let z = range(rng, -1., 1.);
+    let phi = range(rng, 0., 2. * PI);
+    let r = (1. - z * z).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+fn primitive(rng: &mut Rng) -> Box<dyn Sdf> {
+    let surface = Surface::new(Vec3::one(), 0.);
+    let shape: Box<dyn Sdf> = if rng.next_f32() < 0.5 {
+        Box::new(Sphere {
+            center: point(rng, 10.),
+            radius: range(rng, 1., 30.),
+            surface,
+        })
+    } else {
+        Box::new(Capsule {
+            a: point(rng, 20.),
+            b: point(rng, 20.),
+            radius: range(rng, 1., 15.),
+            surface,
+        })
+    };
+    Box::new(Transform {
+        offset: point(rng, OBJECT_SPREAD),
+        rotation: Rotor3::from_euler_angles(
+            range(rng, -PI, PI),
+            range(rng, -PI, PI),
+            range(rng, -PI, PI),
+        ),
+        scale: range(rng, 0.2, 2.),
+        child: shape,
+    })
+}
+
+fn random_scene(rng: &mut Rng) -> Scene {
+    let count = 1 + rng.next_u32() as usize % 8;
+    let mut sdf = primitive(rng);
+    for _ in 1..count {
+        sdf = Box::new(Union(sdf, primitive(rng)));
+    }
+    let mut scene = Scene::new(sdf, Vec::new());
+    // Some scenes are left unbounded, for rays to give up by distance
+    if rng.next_f32() < 0.7 {
+        let half = Vec3::broadcast(HALF_SIZE);
+        scene.bounds = Some((-half, half));
+    }
+    scene
+}
+
+// Runs the check on each ray of each random scene
+fn for_each_ray(check: impl Fn(&Scene, Vec3, Vec3, &str)) {
+    for case in 0..CASES {
+        let mut rng = Rng::new(case);
+        let scene = random_scene(&mut rng);
+        for ray in 0..RAYS_PER_CASE {
+            // Among the objects half the time, so that some start inside
+            let spread = if rng.next_f32() < 0.5 {
+                OBJECT_SPREAD
+            } else {
+                HALF_SIZE * 0.9
+            };
+            let from = point(&mut rng, spread);
+            let dir = direction(&mut rng, from);
+            let what = format!("case {} ray {} from {:?} along {:?}", case, ray, from, dir);
+            check(&scene, from, dir, &what);
+        }
+    }
+}
+
+#[test]
+fn terminates() {
+    for_each_ray(|scene, from, dir, what| {
+        // Every step is at least the precision at the start, and rays give
+        // up across the bounds, or after 1000 units without them
+        let furthest = (3f32.sqrt() * 2. * HALF_SIZE).max(1000.);
+        let cap = (furthest / query::precision(scene, 0.)).ceil() as usize + 1;
+        let steps = query::steps(scene, from, dir);
+        assert!(steps <= cap, "{}: {} steps", what, steps);
+    });
+}
+
+#[test]
+fn hits_are_at_the_surface() {
+    for_each_ray(|scene, from, dir, what| {
+        if scene.sample(from).distance <= 0. {
+            return;
+        }
+        let hit = match query::cast(scene, from, dir) {
+            Some(hit) => hit,
+            None => return,
+        };
+        let distance = scene.sample(hit.p).distance;
+        let threshold = query::precision(scene, hit.distance) * (1. + TOLERANCE);
+        assert!(
+            (-threshold..=0.).contains(&distance),
+            "{}: hit {:?} is {} from the surface, more than {}",
+            what,
+            hit.p,
+            distance,
+            threshold
+        );
+        let off_ray = (hit.p - from - dir * hit.distance).mag();
+        assert!(off_ray <= TOLERANCE * hit.distance.max(1.), "{}", what);
+    });
+}
+
+#[test]
+fn normals_are_unit_length() {
+    for_each_ray(|scene, from, dir, what| {
+        let hit = match query::cast(scene, from, dir) {
+            Some(hit) => hit,
+            None => return,
+        };
+        let length = hit.n.mag();
+        assert!(
+            (length - 1.).abs() <= TOLERANCE,
+            "{}: normal {:?} at {:?} has length {}",
+            what,
+            hit.n,
+            hit.p,
+            length
+        );
+    });
+}
+
+#[test]
+fn normals_at_any_point_are_unit_length() {
+    // Including points deep inside objects and in the middle of spheres,
+    // where the field is flat or has no gradient
+    for case in 0..CASES {
+        let mut rng = Rng::new(case);
+        let scene = random_scene(&mut rng);
+        for _ in 0..RAYS_PER_CASE {
+            let p = point(&mut rng, HALF_SIZE);
+            let n = query::normal(&scene, p, range(&mut rng, 0., 500.));
+            assert!(
+                (n.mag() - 1.).abs() <= TOLERANCE,
+                "case {}: {:?} at {:?}",
+                case,
+                n,
+                p
+            );
+        }
+    }
+    let sphere = Sphere {
+        center: Vec3::zero(),
+        radius: 1.,
+        surface: Surface::new(Vec3::one(), 0.),
+    };
+    let n = query::normal(&Scene::new(sphere, Vec::new()), Vec3::zero(), 0.);
+    assert!(
+        (n.mag() - 1.).abs() <= TOLERANCE,
+        "center of a sphere: {:?}",
+        n
+    );
+}
+
+#[test]
+fn leaving_ends_outside() {
+    for_each_ray(|scene, from, dir, what| {
+        let p = match query::leave(scene, from, dir) {
+            Some(p) => p,
+            None => return,
+        };
+        let distance = scene.sample(p).distance;
+        if scene.sample(from).distance > 0. {
+            assert_eq!(p, from, "{}: already outside", what);
+        } else {
+            assert!(distance > 0., "{}: {:?} is {} inside", what, p, -distance);
+        }
+        let along = (p - from).dot(dir);
+        let off_ray = (p - from - dir * along).mag();
+        assert!(along >= 0., "{}: went back to {:?}", what, p);
+        assert!(off_ray <= TOLERANCE * along.max(1.), "{}", what);
+    });
+}
@@ -0,0 +1,196 @@
+// Saves scenes to files and loads them again, checking that nothing is lost
+// on the way: the loaded scene has the same fingerprint, renders the same,
+// and saves to the same file.
+
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use ultraviolet::Vec3;
+
+use raycast::distfield::Surface;
+use raycast::graph::Sphere;
+use raycast::sky::Sky;
+use raycast::{render_rgba, scenes, ClipPlane, Light, Scene, Settings};
+
+fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.json", name))
+}
+
+fn round_trip(name: &str, scene: &Scene) {
+    let first = path(name);
+    scene.save(&first).unwrap();
+    let loaded = Scene::load(&first).unwrap();
+    assert_eq!(scene.fingerprint(), loaded.fingerprint(), "{}", name);
+
+    let settings = Settings::default();
+    assert!(
+        render_rgba(scene, &settings, 32, 24) == render_rgba(&loaded, &settings, 32, 24),
+        "{} renders differently after loading",
+        name
+    );
+
+    let second = path(&format!("{}-again", name));
+    loaded.save(&second).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&first).unwrap(),
+        std::fs::read_to_string(&second).unwrap(),
+        "{}",
+        name
+    );
+}
+
+#[test]
+fn built_in_scenes() {
+    for name in ["graph", "generated"] {
+        round_trip(name, &scenes::by_name(name).unwrap());
+    }
+}
+
+#[test]
+fn everything_else_in_a_scene() {
+    let surface = Surface::new(Vec3::new(0.2, 0.4, 0.6), 0.3)
+        .with_refraction(1.5)
+        .with_dispersion(0.0042)
+        .with_absorption(Vec3::new(0.9, 0.5, 0.1), 20.)
+        .with_bump(3., 0.7)
+        .with_object(7);
+    let sphere = Sphere {
+        center: Vec3::new(0., 0., 100.),
+        radius: 1. / 3.,
+        surface,
+    };
+    let mut scene = Scene::new(sphere, Vec::new());
+    let mask = scene.light_groups.mask("key").unwrap();
+    scene.lights = vec![
+        Light::area(Vec3::new(10., 20., 30.), 5., Vec3::new(0.7, 0.8, 0.9)).with_groups(mask),
+        Light::directional(Vec3::new(1., 2., -3.), Vec3::one())
+            .with_shadow_tint(Vec3::broadcast(0.1)),
+    ];
+    scene.sky = Some(Sky::preetham(Vec3::new(0.3, 0.4, -1.), 2.5));
+    scene.clip_planes = vec![ClipPlane::new(Vec3::new(1., 1., 0.), 0.1).with_cap(surface)];
+    scene.shadow_tint = Some(Vec3::new(0.1, 0.2, 0.3));
+    scene.unit_scale = 0.01;
+    scene.bounds = Some((Vec3::broadcast(-150.), Vec3::broadcast(150.)));
+    round_trip("everything", &scene);
+
+    scene.sky = Some(
+        Sky::gradient(Vec3::unit_z(), Vec3::unit_y(), Vec3::unit_x()).with_sun(
+            Vec3::new(1., 1., 1.),
+            0.05,
+            Vec3::broadcast(10.),
+        ),
+    );
+    round_trip("gradient", &scene);
+}
+
+#[test]
+fn unsupported_scenes_are_not_saved() {
+    // Plain functions can't be saved
+    let scene = scenes::by_name("default").unwrap();
+    let err = scene.save(path("default")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+    let sphere = || Sphere {
+        center: Vec3::zero(),
+        radius: 1.,
+        surface: Surface::new(Vec3::one(), 0.).with_object(1),
+    };
+    let mut scene = Scene::new(sphere(), Vec::new());
+    scene.set_shader(raycast::distfield::ObjectId(1), |_| Vec3::one());
+    let err = scene.save(path("shader")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+    let mut scene = Scene::new(sphere(), Vec::new());
+    scene.unit_scale = f32::INFINITY;
+    let err = scene.save(path("infinite")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn invalid_files_are_rejected() {
+    // Deep enough to overflow the stack if parsing recursed into all of it
+    let deep = "[".repeat(200_000);
+    for (name, text) in [
+        ("not-json", "{ \"version\": 1, "),
+        ("no-version", "{ \"ops\": [] }"),
+        ("no-ops", "{ \"version\": 1, \"ops\": [] }"),
+        (
+            "unbalanced",
+            r#"{ "version": 1, "ops": [{ "op": "union" }] }"#,
+        ),
+        (
+            "union-of-one",
+            r#"{ "version": 1, "ops": [
+                { "op": "sphere", "center": [0, 0, 0], "radius": 1, "surface": {} },
+                { "op": "union" },
+                { "op": "sphere", "center": [0, 0, 0], "radius": 1, "surface": {} }
+            ] }"#,
+        ),
+        (
+            "invert-nothing",
+            r#"{ "version": 1, "ops": [
+                { "op": "invert" },
+                { "op": "sphere", "center": [0, 0, 0], "radius": 1, "surface": {} }
+            ] }"#,
+        ),
+        (
+            "pop-nothing",
+            r#"{ "version": 1, "ops": [
+                { "op": "sphere", "center": [0, 0, 0], "radius": 1, "surface": {} },
+                { "op": "pop_transform", "scale": 1 }
+            ] }"#,
+        ),
+        (
+            "bad-radius",
+            r#"{ "version": 1, "ops": [{ "op": "sphere", "center": [0, 0, 0], "radius": "big", "surface": {} }] }"#,
+        ),
+        ("too-deep", &deep),
+        (
+            "too-large",
+            r#"{ "version": 1, "ops": [{ "op": "sphere", "center": [0, 0, 0], "radius": 1e999, "surface": {} }] }"#,
+        ),
+        (
+            "no-scale",
+            r#"{ "version": 1, "ops": [{ "op": "sphere", "center": [0, 0, 0], "radius": 1, "surface": {} }], "unit_scale": 0 }"#,
+        ),
+        (
+            "inverted-bounds",
+            r#"{ "version": 1, "ops": [{ "op": "sphere", "center": [0, 0, 0], "radius": 1, "surface": {} }], "bounds": [[1, 0, 0], [0, 1, 1]] }"#,
+        ),
+        (
+            "no-normal",
+            r#"{ "version": 1, "ops": [{ "op": "sphere", "center": [0, 0, 0], "radius": 1, "surface": {} }], "clip_planes": [{ "normal": [0, 0, 0], "offset": 0 }] }"#,
+        ),
+    ] {
+        let file = path(name);
+        std::fs::write(&file, text).unwrap();
+        let err = Scene::load(&file).err();
+        assert_eq!(
+            err.map(|err| err.kind()),
+            Some(ErrorKind::InvalidData),
+            "{}",
+            name
+        );
+    }
+
+    // Surfaces only need what differs from the defaults
+    let file = path("minimal");
+    std::fs::write(
+        &file,
+        r#"{ "version": 1, "ops": [{ "op": "sphere", "center": [0, 0, 0], "radius": 2, "surface": {} }] }"#,
+    )
+    .unwrap();
+    let scene = Scene::load(&file).unwrap();
+    assert_eq!(scene.sample(Vec3::zero()).distance, -2.);
+
+    // Directions are normalized as the constructors do
+    let file = path("unnormalized");
+    std::fs::write(
+        &file,
+        r#"{ "version": 1, "ops": [{ "op": "sphere", "center": [0, 0, 0], "radius": 2, "surface": {} }],
+            "clip_planes": [{ "normal": [0, 0, 2], "offset": 1 }] }"#,
+    )
+    .unwrap();
+    let scene = Scene::load(&file).unwrap();
+    assert_eq!(scene.clip_planes[0].normal, Vec3::unit_z());
+}